@@ -57,6 +57,33 @@ macro_rules! byte_field {
             }
         }
 
+        impl ::serde::Serialize for $struct_name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut bytes = Vec::with_capacity(Self::len());
+                $(
+                    bytes.extend_from_slice(&self.$field_name);
+                )*
+                serializer.serialize_bytes(&bytes)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $struct_name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let bytes = <Vec<u8> as ::serde::Deserialize>::deserialize(deserializer)?;
+                if bytes.len() != Self::len() {
+                    return Err(<D::Error as ::serde::de::Error>::invalid_length(
+                        bytes.len(),
+                        &stringify!($struct_name),
+                    ));
+                }
+                let mut s = Self::new_empty();
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    s[i] = byte;
+                }
+                return Ok(s);
+            }
+        }
+
         impl From<[u8; Self::len()]> for $struct_name {
             fn from(value: [u8; Self::len()]) -> Self {
                 let mut s = Self::new_empty();
@@ -68,3 +95,33 @@ macro_rules! byte_field {
         }
     };
 }
+
+/// Like [`byte_field`], but generates named accessors into the consecutive bytes of an existing `Vec<u8>`/slice field rather than owning the arrays.
+#[macro_export]
+macro_rules! byte_view {
+    (
+        impl $struct_name:ident => $data:ident;
+        $($(#[$field_attr:meta])* $field_vis:vis $field_name:ident: $length:expr),* $(,)?
+    ) => {
+        impl $struct_name {
+            $crate::byte_view!(@field $data, 0; $($(#[$field_attr])* $field_vis $field_name: $length),*);
+
+            const fn view_len() -> usize {
+                return 0 $(+ $length)*;
+            }
+        }
+    };
+
+    (@field $data:ident, $offset:expr; ) => {};
+
+    (@field $data:ident, $offset:expr;
+        $(#[$field_attr:meta])* $field_vis:vis $field_name:ident: $length:expr $(, $($rest:tt)*)?
+    ) => {
+        $(#[$field_attr])*
+        $field_vis fn $field_name(&self) -> &[u8; $length] {
+            self.$data[$offset..$offset + $length].try_into().unwrap()
+        }
+
+        $crate::byte_view!(@field $data, $offset + $length; $($($rest)*)?);
+    };
+}
@@ -0,0 +1,209 @@
+#![allow(non_snake_case)]
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{emu::GameboyEmulator, io::io_registers::IORegisters, utils::*};
+
+/// The size of the DMG, MGB and SGB boot ROMs, mapped over `0x0000..=0x00FF`.
+pub const DMG_BOOT_ROM_SIZE: usize = 0x0100;
+/// The size of the CGB and AGB boot ROMs, which are also mapped over `0x0200..=0x08FF`.
+pub const CGB_BOOT_ROM_SIZE: usize = 0x0900;
+
+/// The hardware being emulated, which decides the state the boot ROM leaves behind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Model {
+    /// The earliest DMG boot ROM.
+    DMG0,
+    #[default]
+    DMG,
+    /// Game Boy Pocket and Game Boy Light.
+    MGB,
+    /// Super Game Boy.
+    SGB,
+    /// Game Boy Color.
+    CGB,
+    /// Game Boy Advance, running Game Boy Color software.
+    AGB,
+}
+
+impl Model {
+    /// Returns `true` for models that can run in CGB mode.
+    #[inline]
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::CGB | Model::AGB)
+    }
+
+    /// Returns the size a boot ROM dumped from this model should be.
+    #[inline]
+    pub fn get_boot_rom_size(&self) -> usize {
+        match self.is_cgb() {
+            true => CGB_BOOT_ROM_SIZE,
+            false => DMG_BOOT_ROM_SIZE,
+        }
+    }
+
+    /// [pandocs](https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers), `A`, `F`, `B`, `C`, `D`, `E`, `H` and `L` after boot.
+    ///
+    /// Some models set the half-carry and carry flags unless the cartridge's `header_checksum` is `0x00`.
+    pub fn get_post_boot_registers(&self, header_checksum: u8) -> [u8; 8] {
+        let checksum_flags = match header_checksum {
+            0x00 => 0x00,
+            _ => Flag::H | Flag::C,
+        };
+        match self {
+            Model::DMG0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+            Model::DMG => [
+                0x01,
+                0x80 | checksum_flags,
+                0x00,
+                0x13,
+                0x00,
+                0xD8,
+                0x01,
+                0x4D,
+            ],
+            Model::MGB => [
+                0xFF,
+                0x80 | checksum_flags,
+                0x00,
+                0x13,
+                0x00,
+                0xD8,
+                0x01,
+                0x4D,
+            ],
+            Model::SGB => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::CGB => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::AGB => [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        }
+    }
+
+    /// The internal `DIV` counter when the boot ROM hands over to the cartridge.
+    ///
+    /// Only the upper byte (what `DIV` reads) is documented for the DMG0, DMG and MGB,
+    /// other models depend on how long the logo animation ran for.
+    pub fn get_post_boot_DIV(&self) -> u16 {
+        match self {
+            Model::DMG0 => 0x1800,
+            Model::DMG | Model::MGB => 0xABCC,
+            Model::SGB | Model::CGB | Model::AGB => 0x0000,
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg0" => Ok(Model::DMG0),
+            "dmg" => Ok(Model::DMG),
+            "mgb" => Ok(Model::MGB),
+            "sgb" => Ok(Model::SGB),
+            "cgb" => Ok(Model::CGB),
+            "agb" => Ok(Model::AGB),
+            _ => Err(format!(
+                "Unknown model {s}, expected dmg0, dmg, mgb, sgb, cgb or agb"
+            )),
+        }
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers), IO register indices and values after boot,
+/// written in order so the APU is powered on before its registers are set.
+const POST_BOOT_IO: [(usize, u8); 24] = [
+    (0x0000, 0x00),
+    (0x0007, 0xF8),
+    (0x000F, 0x01),
+    (0x0026, 0x80),
+    (0x0010, 0x80),
+    (0x0011, 0xBF),
+    (0x0012, 0xF3),
+    (0x0013, 0xFF),
+    (0x0016, 0x3F),
+    (0x0017, 0x00),
+    (0x0018, 0xFF),
+    (0x001A, 0x7F),
+    (0x001B, 0xFF),
+    (0x001C, 0x9F),
+    (0x001D, 0xFF),
+    (0x0020, 0xFF),
+    (0x0021, 0x00),
+    (0x0022, 0x00),
+    (0x0024, 0x77),
+    (0x0025, 0xF3),
+    (0x0040, 0x91),
+    (0x0047, 0xFC),
+    (0x0050, 0x01),
+    (0x00FF, 0x00),
+];
+
+/// Reads a boot ROM dump for `model`, checking that it is the right size.
+pub fn load_boot_rom(path: impl AsRef<Path>, model: Model) -> std::io::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    let size = model.get_boot_rom_size();
+    if data.len() != size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "GB - {model:?} boot ROM should be {size} bytes, not {}!",
+                data.len()
+            ),
+        ));
+    }
+    Ok(data)
+}
+
+impl GameboyEmulator {
+    /// Puts the machine in the state the boot ROM leaves it in, ready to start the cartridge at `0x0100`.
+    pub fn skip_boot_rom(&mut self) {
+        let header_checksum = self.bus.cartridge.header_checksum()[0];
+        let registers = self.model.get_post_boot_registers(header_checksum);
+        for (register, value) in [
+            Register::A,
+            Register::F,
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+        ]
+        .into_iter()
+        .zip(registers)
+        {
+            self.cpu.set_register(register, value);
+        }
+        self.cpu.set_register_pair(RegisterPair::PC, 0x0100);
+        self.cpu.set_register_pair(RegisterPair::SP, 0xFFFE);
+
+        for (index, value) in POST_BOOT_IO {
+            IORegisters::write(self, index, value);
+        }
+        self.io_registers.serial.SC = match self.model.is_cgb() {
+            true => 0x7F,
+            false => 0x7E,
+        };
+        // ? Channel 1 is still on after playing the boot sound, except on the SGB which doesn't play it.
+        self.io_registers.audio.channel1.enabled = self.model != Model::SGB;
+        self.io_registers.graphics.DMA = 0xFF;
+        if self.model.is_cgb() {
+            // ? The CGB boot ROM only leaves CGB mode on for games that support it, and clears the color palettes to white.
+            let cgb_flag = self.bus.cartridge.get_cgb_flag();
+            self.io_registers.KEY0 = match cgb_flag & 0x80 {
+                0x00 => 0x04,
+                _ => cgb_flag,
+            };
+            let graphics = &mut self.io_registers.graphics;
+            for i in 0..64 {
+                graphics.bg_palette_ram[i] = 0xFF;
+                graphics.obj_palette_ram[i] = 0xFF;
+            }
+        }
+        self.io_registers.timer.DIV = self.model.get_post_boot_DIV();
+    }
+}
@@ -1,32 +1,33 @@
 use super::{
-    cartridge::Cartridge,
-    emu::GameboyEmulator,
-    io::graphics::{OAM, VRAM},
+    cartridge::{Cartridge, MBC},
+    emu::{EmulatorEvent, GameboyEmulator, SAVE_FLUSH_DELAY},
+    io::{
+        graphics::{OAM, PPU, VRAM, VRAM_BANK_SIZE},
+        io_registers::IORegisters,
+    },
 };
-#[cfg(not(test))]
-use super::{io::io_registers::IORegisters, utils::join_u16};
 use crate::byte_field;
 
-#[cfg(not(test))]
-static mut BOOT_ROM: [u8; 256] = *include_bytes!("../../roms/gb/GB Boot ROM.bin");
-
-#[cfg(test)]
-static mut TEST_RAM: [u8; 65536] = [0x00; 65536];
-
 #[derive(Debug)]
 pub struct Bus {
     pub cartridge: Cartridge,
+    pub mbc: MBC,
+    /// Mapped over the start of the cartridge until `0xFF50` is written, see [`crate::gb::boot`].
+    pub boot_rom: Option<Vec<u8>>,
     pub vram: VRAM,
     pub wram: WRAM,
     pub oam: OAM,
     pub hram: HRAM,
+    /// Replaces the whole address space with flat RAM when set, for CPU tests which don't need the rest of the system.
+    #[cfg(test)]
+    pub test_ram: Option<Box<[u8; 65536]>>,
 }
 
 byte_field! {
-    /// General purpose work RAM.
+    /// General purpose work RAM, 8 banks of 4KiB of which only the first 2 are used outside of CGB mode.
     #[derive(Debug)]
     pub WRAM;
-    pub ram: 8192,
+    pub ram: 32768,
 }
 
 byte_field! {
@@ -37,88 +38,130 @@ byte_field! {
 }
 
 impl Bus {
-    pub fn read(
-        #[allow(unused_variables)] emu: &mut GameboyEmulator,
-        #[allow(unused_mut)] mut address: u16,
-    ) -> u8 {
+    pub fn read(emu: &mut GameboyEmulator, address: u16) -> u8 {
         #[cfg(test)]
-        {
-            return unsafe { TEST_RAM[address as usize] };
+        if let Some(ram) = &emu.bus.test_ram {
+            return ram[address as usize];
         }
 
-        #[cfg(not(test))]
-        {
-            // ? DMA transfer in progress: R/W is not functional (except for HRAM = 0xFF80..=0xFFFE).
-            if let Some(index) = emu.io_registers.graphics.DMA_transfer_progress {
-                if index != 0x00 && !(0xFF80..=0xFFFE).contains(&address) {
-                    // ? Reads during DMA transfer actually return the byte being transferred.
-                    address = join_u16(index, emu.io_registers.graphics.DMA);
-                }
-            }
+        // ? During OAM DMA, OAM can't be read and reads from the same bus as the source see the byte being transferred.
+        if let Some(index) = emu.io_registers.graphics.DMA_transfer_progress {
+            let source = emu.io_registers.graphics.get_DMA_address(index);
             match address {
-                0x0000..=0x00FF => {
-                    if Self::read(emu, 0xFF50) == 0x00 {
-                        // ? Boot ROM is still mapped.
-                        unsafe { BOOT_ROM[address as usize] }
-                    } else {
-                        emu.bus.cartridge[address as usize]
-                    }
-                }
-                0x0100..=0x3FFF => emu.bus.cartridge[address as usize],
-                0x4000..=0x7FFF => todo!("GB - Swappable ROM"),
-                0x8000..=0x9FFF => emu.bus.vram[address as usize - 0x8000],
-                0xA000..=0xBFFF => todo!("GB - Swappable RAM"),
-                0xC000..=0xDFFF => emu.bus.wram[address as usize - 0xC000],
-                0xE000..=0xFDFF => emu.bus.wram[address as usize - 0xE000],
-                0xFE00..=0xFE9F => emu.bus.oam[address as usize - 0xFE00],
-                0xFEA0..=0xFEFF => 0xFF, // ? unimplemented!("GB - 0xFEA0..=0xFEFF not usable!")
-                0xFF00..=0xFF7F => IORegisters::read(emu, address as usize - 0xFF00),
-                0xFF80..=0xFFFE => emu.bus.hram[address as usize - 0xFF80],
-                0xFFFF => IORegisters::read(emu, address as usize - 0xFF00),
+                0xFE00..=0xFEFF => return 0xFF,
+                _ if Self::is_same_bus(address, source) => return Self::read_direct(emu, source),
+                _ => {}
             }
         }
+        if !PPU::can_access(emu, address) {
+            return 0xFF;
+        }
+        Self::read_direct(emu, address)
     }
 
-    pub fn write(#[allow(unused_variables)] emu: &mut GameboyEmulator, address: u16, value: u8) {
+    /// Reads `address` without any OAM DMA conflicts, as the DMA controller itself does.
+    pub fn read_direct(emu: &mut GameboyEmulator, address: u16) -> u8 {
         #[cfg(test)]
-        {
-            return unsafe {
-                TEST_RAM[address as usize] = value;
-            };
+        if let Some(ram) = &emu.bus.test_ram {
+            return ram[address as usize];
         }
 
-        #[cfg(not(test))]
-        {
-            // ? DMA transfer in progress: R/W is not functional (except for HRAM = 0xFF80..=0xFFFE).
-            if emu
-                .io_registers
-                .graphics
-                .DMA_transfer_progress
-                .is_some_and(|i| i != 0x00)
-                && !(0xFF80..=0xFFFE).contains(&address)
-            {
+        match address {
+            0x0000..=0x7FFF => match Self::read_boot_rom(emu, address) {
+                Some(value) => value,
+                None => emu.bus.mbc.read_rom(&emu.bus.cartridge, address),
+            },
+            0x8000..=0x9FFF => emu.bus.vram[Self::get_vram_index(emu, address)],
+            0xA000..=0xBFFF => emu.bus.mbc.read_ram(address),
+            0xC000..=0xFDFF => emu.bus.wram[Self::get_wram_index(emu, address)],
+            0xFE00..=0xFE9F => emu.bus.oam[address as usize - 0xFE00],
+            0xFEA0..=0xFEFF => 0xFF, // ? unimplemented!("GB - 0xFEA0..=0xFEFF not usable!")
+            0xFF00..=0xFF7F => IORegisters::read(emu, address as usize - 0xFF00),
+            0xFF80..=0xFFFE => emu.bus.hram[address as usize - 0xFF80],
+            0xFFFF => IORegisters::read(emu, address as usize - 0xFF00),
+        }
+    }
+
+    pub fn write(emu: &mut GameboyEmulator, address: u16, value: u8) {
+        #[cfg(test)]
+        if let Some(ram) = &mut emu.bus.test_ram {
+            ram[address as usize] = value;
+            return;
+        }
+
+        // ? During OAM DMA, writes to OAM or to the same bus as the source are lost.
+        if let Some(index) = emu.io_registers.graphics.DMA_transfer_progress {
+            let source = emu.io_registers.graphics.get_DMA_address(index);
+            if (0xFE00..=0xFEFF).contains(&address) || Self::is_same_bus(address, source) {
                 return;
             }
+        }
+        if !PPU::can_access(emu, address) {
+            return;
+        }
 
-            match address {
-                0x0000..=0x00FF => {}
-                0x0100..=0x3FFF => emu.bus.cartridge[address as usize - 0x0100] = value,
-                0x4000..=0x7FFF => {}
-                0x8000..=0x9FFF => emu.bus.vram[address as usize - 0x8000] = value,
-                0xA000..=0xBFFF => todo!("GB - Swappable RAM"),
-                0xC000..=0xDFFF => emu.bus.wram[address as usize - 0xC000] = value,
-                0xE000..=0xFDFF => emu.bus.wram[address as usize - 0xE000] = value,
-                0xFE00..=0xFE9F => emu.bus.oam[address as usize - 0xFE00] = value,
-                0xFEA0..=0xFEFF => {} // ? unimplemented!("GB - 0xFEA0..=0xFEFF not usable!")
-                0xFF00..=0xFF7F => IORegisters::write(emu, address as usize - 0xFF00, value),
-                0xFF80..=0xFFFE => emu.bus.hram[address as usize - 0xFF80] = value,
-                0xFFFF => IORegisters::write(emu, address as usize - 0xFF00, value),
+        match address {
+            0x0000..=0x7FFF => {
+                let was_rumbling = emu.bus.mbc.is_rumbling();
+                emu.bus.mbc.write_rom(address, value);
+                let is_rumbling = emu.bus.mbc.is_rumbling();
+                if was_rumbling != is_rumbling {
+                    emu.push_event(EmulatorEvent::Rumble(is_rumbling));
+                }
+            }
+            0x8000..=0x9FFF => {
+                let index = Self::get_vram_index(emu, address);
+                emu.bus.vram[index] = value
+            }
+            0xA000..=0xBFFF => {
+                emu.bus.mbc.write_ram(address, value);
+                if emu.bus.cartridge.save_path.is_some() {
+                    emu.save_flush_timer = Some(SAVE_FLUSH_DELAY);
+                }
+            }
+            0xC000..=0xFDFF => {
+                let index = Self::get_wram_index(emu, address);
+                emu.bus.wram[index] = value
             }
+            0xFE00..=0xFE9F => emu.bus.oam[address as usize - 0xFE00] = value,
+            0xFEA0..=0xFEFF => {} // ? unimplemented!("GB - 0xFEA0..=0xFEFF not usable!")
+            0xFF00..=0xFF7F => IORegisters::write(emu, address as usize - 0xFF00, value),
+            0xFF80..=0xFFFE => emu.bus.hram[address as usize - 0xFF80] = value,
+            0xFFFF => IORegisters::write(emu, address as usize - 0xFF00, value),
         }
     }
 
-    #[cfg(test)]
-    pub fn reset_test_ram() -> [u8; 65536] {
-        unsafe { std::mem::replace(&mut TEST_RAM, [0x00; 65536]) }
+    /// Returns `true` if `a` and `b` are both on the external bus (cartridge and WRAM) or both on the VRAM bus.
+    fn is_same_bus(a: u16, b: u16) -> bool {
+        let is_vram = |address| (0x8000..=0x9FFF).contains(&address);
+        // ? OAM, IO and HRAM are inside the CPU.
+        a < 0xFE00 && b < 0xFE00 && is_vram(a) == is_vram(b)
+    }
+
+    /// Returns the index into VRAM of `address` in the bank selected by `VBK`.
+    fn get_vram_index(emu: &GameboyEmulator, address: u16) -> usize {
+        (emu.io_registers.graphics.VBK & 0b1) as usize * VRAM_BANK_SIZE
+            + (address & 0x1FFF) as usize
+    }
+
+    /// Returns the index into WRAM of `address` (including echo RAM), `0xD000..=0xDFFF` being the bank selected by `SVBK`.
+    fn get_wram_index(emu: &GameboyEmulator, address: u16) -> usize {
+        let offset = (address & 0x0FFF) as usize;
+        match address & 0x1000 {
+            0x0000 => offset,
+            _ => match emu.is_cgb_mode() {
+                true => (emu.io_registers.SVBK & 0b111).max(1) as usize * 0x1000 + offset,
+                false => 0x1000 + offset,
+            },
+        }
+    }
+
+    /// Returns the boot ROM's byte at `address` while it is still mapped.
+    fn read_boot_rom(emu: &GameboyEmulator, address: u16) -> Option<u8> {
+        // ? The CGB boot ROM leaves a gap for the cartridge header.
+        if emu.io_registers.boot_rom_control != 0x00 || (0x0100..=0x01FF).contains(&address) {
+            return None;
+        }
+        emu.bus.boot_rom.as_ref()?.get(address as usize).copied()
     }
 }
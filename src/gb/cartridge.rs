@@ -1,10 +1,24 @@
-use crate::byte_field;
+use crate::byte_view;
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::string::FromUtf8Error;
 
-byte_field! {
-    /// [pandocs](https://gbdev.io/pandocs/The_Cartridge_Header.html).
-    #[derive(Debug)]
-    pub Cartridge;
+use super::mbc::{mbc1::MBC1, mbc3::MBC3, mbc5::MBC5};
+
+/// [pandocs](https://gbdev.io/pandocs/The_Cartridge_Header.html).
+///
+/// Only built by [`Cartridge::from_rom`] (or [`Cartridge::new_empty`]), so the header is always supported.
+#[derive(Debug)]
+pub struct Cartridge {
+    /// The entire ROM image, a multiple of 16KiB banks.
+    rom: Vec<u8>,
+    /// Where battery-backed RAM is persisted, `None` for carts without a battery or ROMs not loaded from a file.
+    pub save_path: Option<PathBuf>,
+}
+
+byte_view! {
+    impl Cartridge => rom;
     pub restart_vectors:   256,
     pub entry_point:       4,
     pub nintendo_logo:     48,
@@ -23,53 +37,261 @@ byte_field! {
 }
 
 impl Cartridge {
-    pub fn read(&self, address: u16) -> u8 {
-        self[(address - 0x0100) as usize]
+    /// An empty 32KiB ROM-only cartridge.
+    pub fn new_empty() -> Self {
+        Self {
+            rom: vec![0x00; 0x8000],
+            save_path: None,
+        }
+    }
+
+    /// Read a byte from the ROM at `index`, returning `0xFF` if it is outside of the ROM.
+    #[inline]
+    pub fn read_rom(&self, index: usize) -> u8 {
+        self.rom.get(index).copied().unwrap_or(0xFF)
+    }
+
+    /// Creates a cartridge from a full ROM image, checking its length against the `rom_size` header byte
+    /// and that its `cartridge_type` and `ram_size` are supported.
+    pub fn from_rom(rom: Vec<u8>) -> std::io::Result<Self> {
+        if rom.len() < Self::view_len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("GB - ROM is smaller than the first bank ({} bytes)!", rom.len()),
+            ));
+        }
+        let cartridge = Self {
+            rom,
+            save_path: None,
+        };
+        let rom_size = cartridge.rom_size()[0];
+        if rom_size > 0x08 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("GB - Cartridge ROM size {:#04X} not recognised!", rom_size),
+            ));
+        }
+        let ram_size = cartridge.ram_size()[0];
+        if ram_size > 0x05 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("GB - Cartridge RAM size {:#04X} not recognised!", ram_size),
+            ));
+        }
+        let cartridge_type = cartridge.cartridge_type()[0];
+        if !Self::is_supported_type(cartridge_type) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("GB - Cartridge type {:#04X} not supported!", cartridge_type),
+            ));
+        }
+        if cartridge.rom.len() != cartridge.get_rom_size() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "GB - ROM is {} bytes, but its header specifies {} bytes!",
+                    cartridge.rom.len(),
+                    cartridge.get_rom_size()
+                ),
+            ));
+        }
+        Ok(cartridge)
+    }
+
+    /// Loads a ROM image, with battery-backed RAM saved to a `.sav` file next to it.
+    pub fn load_from_file(file_path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut cartridge = Self::from_rom(std::fs::read(&file_path)?)?;
+        if cartridge.has_battery() {
+            cartridge.save_path = Some(file_path.as_ref().with_extension("sav"));
+        }
+        Ok(cartridge)
     }
 
-    #[allow(unused_variables)]
-    pub fn write(&self, address: u16, value: u8) {
-        panic!("GB - Cannot write to the cartridge header ROM!");
+    /// Returns `true` if [`Cartridge::get_mbc`] can emulate the `cartridge_type` header byte.
+    fn is_supported_type(cartridge_type: u8) -> bool {
+        matches!(cartridge_type, 0x00..=0x03 | 0x08 | 0x09 | 0x0F..=0x13 | 0x19..=0x1E)
     }
 
-    pub fn load_from_file(file_path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
-        let mut file = std::fs::File::open(&file_path)?;
-        let mut buffer = [0; Self::len()];
-        std::io::Read::read_exact(&mut file, &mut buffer)?;
-        Ok(Self::from(buffer))
+    /// Returns `true` if the `cartridge_type` header byte specifies a battery.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type()[0],
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
     }
 
     /// Returns the game's title.
     pub fn get_title(&self) -> Result<String, FromUtf8Error> {
-        let array = self.title.iter().filter(|&c| *c != 0x00).copied().collect();
+        let array = self.title().iter().filter(|&c| *c != 0x00).copied().collect();
         String::from_utf8(array)
     }
 
+    /// Returns the CGB flag (the last byte of the title), `0x80` if the game supports CGB mode and `0xC0` if it requires it.
+    #[inline]
+    pub fn get_cgb_flag(&self) -> u8 {
+        self.title()[15]
+    }
+
     /// Returns the cartridge's ROM size in bytes.
     pub fn get_rom_size(&self) -> usize {
-        match self.rom_size[0] {
-            0x00 => 32000,
-            0x01 => 64000,
-            0x02 => 128000,
-            0x03 => 256000,
-            0x04 => 512000,
-            0x05 => 1024000,
-            0x06 => 2048000,
-            0x07 => 4096000,
-            _ => panic!("GB - Cartridge ROM size not recognised!"),
-        }
+        // ? `from_rom` rejects sizes above `0x08`.
+        0x8000 << self.rom_size()[0].min(0x08)
     }
 
     /// Returns the cartridge's RAM size in bytes.
     pub fn get_ram_size(&self) -> usize {
-        match self.ram_size[0] {
-            0x00 => 0,
-            0x01 => 2000,
-            0x02 => 8000,
-            0x03 => 32000,
-            0x04 => 128000,
-            0x05 => 64000,
-            _ => panic!("GB - Cartridge RAM size not recognised!"),
+        match self.ram_size()[0] {
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            // ? `0x00`, `from_rom` rejects sizes above `0x05`.
+            _ => 0,
+        }
+    }
+
+    /// Returns `true` if this is an MBC1M multicart, detected by a Nintendo logo in the header of ROM bank `0x10`.
+    pub fn is_mbc1_multicart(&self) -> bool {
+        self.get_rom_size() == 0x100000
+            && self.rom[0x40104..0x40134] == self.nintendo_logo()[..]
+    }
+
+    /// Returns the memory bank controller specified by the `cartridge_type` header byte, which [`Cartridge::from_rom`] checks is supported.
+    pub fn get_mbc(&self) -> MBC {
+        let rom_banks = self.get_rom_size() / 0x4000;
+        match self.cartridge_type()[0] {
+            0x01..=0x03 => MBC::MBC1(MBC1::new(
+                rom_banks,
+                self.get_ram_size(),
+                self.is_mbc1_multicart(),
+            )),
+            0x0F..=0x13 => MBC::MBC3(MBC3::new(
+                rom_banks,
+                self.get_ram_size(),
+                matches!(self.cartridge_type()[0], 0x0F | 0x10),
+            )),
+            0x19..=0x1E => MBC::MBC5(MBC5::new(
+                rom_banks,
+                self.get_ram_size(),
+                matches!(self.cartridge_type()[0], 0x1C..=0x1E),
+            )),
+            // ? `0x00`, `0x08` and `0x09`, `from_rom` rejects any other types.
+            _ => MBC::None {
+                ram: vec![0x00; self.get_ram_size()],
+            },
+        }
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/MBCs.html).
+#[derive(Debug, Serialize, Deserialize)]
+pub enum MBC {
+    /// No MBC, 32KiB of ROM and optionally up to 8KiB of RAM.
+    None { ram: Vec<u8> },
+    MBC1(MBC1),
+    MBC3(MBC3),
+    MBC5(MBC5),
+}
+
+impl MBC {
+    /// Update any cartridge hardware as if 4 t-cycles have passed.
+    pub fn update(&mut self) {
+        if let MBC::MBC3(mbc) = self {
+            mbc.update();
+        }
+    }
+
+    /// Read a byte from the ROM area (`0x0000..=0x7FFF`).
+    pub fn read_rom(&self, cartridge: &Cartridge, address: u16) -> u8 {
+        match self {
+            MBC::None { .. } => cartridge.read_rom(address as usize),
+            MBC::MBC1(mbc) => mbc.read_rom(cartridge, address),
+            MBC::MBC3(mbc) => mbc.read_rom(cartridge, address),
+            MBC::MBC5(mbc) => mbc.read_rom(cartridge, address),
+        }
+    }
+
+    /// Write a byte to the ROM area (`0x0000..=0x7FFF`), which sets the MBC's registers.
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match self {
+            MBC::None { .. } => {}
+            MBC::MBC1(mbc) => mbc.write_rom(address, value),
+            MBC::MBC3(mbc) => mbc.write_rom(address, value),
+            MBC::MBC5(mbc) => mbc.write_rom(address, value),
+        }
+    }
+
+    /// Read a byte from external RAM (`0xA000..=0xBFFF`).
+    pub fn read_ram(&self, address: u16) -> u8 {
+        match self {
+            MBC::None { ram } => ram.get(address as usize - 0xA000).copied().unwrap_or(0xFF),
+            MBC::MBC1(mbc) => mbc.read_ram(address),
+            MBC::MBC3(mbc) => mbc.read_ram(address),
+            MBC::MBC5(mbc) => mbc.read_ram(address),
+        }
+    }
+
+    /// Write a byte to external RAM (`0xA000..=0xBFFF`).
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        match self {
+            MBC::None { ram } => {
+                if let Some(byte) = ram.get_mut(address as usize - 0xA000) {
+                    *byte = value;
+                }
+            }
+            MBC::MBC1(mbc) => mbc.write_ram(address, value),
+            MBC::MBC3(mbc) => mbc.write_ram(address, value),
+            MBC::MBC5(mbc) => mbc.write_ram(address, value),
+        }
+    }
+
+    /// Returns `true` while a rumble cart's motor is on.
+    pub fn is_rumbling(&self) -> bool {
+        matches!(self, MBC::MBC5(mbc) if mbc.rumble)
+    }
+
+    /// Returns the external RAM.
+    pub fn get_ram(&self) -> &[u8] {
+        match self {
+            MBC::None { ram } => ram,
+            MBC::MBC1(mbc) => &mbc.ram,
+            MBC::MBC3(mbc) => &mbc.ram,
+            MBC::MBC5(mbc) => &mbc.ram,
+        }
+    }
+
+    /// Returns the external RAM.
+    pub fn get_ram_mut(&mut self) -> &mut [u8] {
+        match self {
+            MBC::None { ram } => ram,
+            MBC::MBC1(mbc) => &mut mbc.ram,
+            MBC::MBC3(mbc) => &mut mbc.ram,
+            MBC::MBC5(mbc) => &mut mbc.ram,
+        }
+    }
+
+    /// Returns the battery-backed data in the layout used by other emulators' save files:
+    /// the raw external RAM, followed by the RTC footer for carts with a clock.
+    pub fn get_save_data(&self) -> Vec<u8> {
+        let mut data = self.get_ram().to_vec();
+        if let MBC::MBC3(mbc) = self {
+            if mbc.has_rtc {
+                data.extend_from_slice(&mbc.get_rtc_footer());
+            }
+        }
+        data
+    }
+
+    /// Loads battery-backed data saved by [`MBC::get_save_data`] (or another emulator).
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let ram = self.get_ram_mut();
+        let ram_len = ram.len().min(data.len());
+        ram[..ram_len].copy_from_slice(&data[..ram_len]);
+        if let MBC::MBC3(mbc) = self {
+            if mbc.has_rtc {
+                mbc.load_rtc_footer(&data[ram_len..]);
+            }
         }
     }
 }
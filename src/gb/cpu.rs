@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use super::utils::*;
 use crate::gb::utils::{split_u16, Register};
 
 /// [pandocs](https://gbdev.io/pandocs/CPU_Registers_and_Flags.html)
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CPU {
     /// Accumulator / register A
     a: u8,
@@ -28,6 +30,7 @@ pub struct CPU {
 
 impl CPU {
     #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(a: u8, f: u8, b: u8, c: u8, d: u8, e: u8, h: u8, l: u8, pc: u16, sp: u16) -> Self {
         Self {
             a,
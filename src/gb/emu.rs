@@ -1,33 +1,201 @@
-use std::rc::Rc;
+use std::collections::VecDeque;
 use std::time::Instant;
-use winit::window::Window;
-use winit_input_helper::WinitInputHelper;
 
+use super::boot::Model;
+use super::bus::{HRAM, WRAM};
+use super::cartridge::Cartridge;
 use super::instructions::operations::INTERRUPT;
-use super::io::graphics::PPU;
+use super::io::audio::{StereoSample, APU};
+use super::io::graphics::{OAM, PPU, SCREEN_HEIGHT, SCREEN_WIDTH, VRAM};
+use super::io::joypad::{JoypadRegisters, JoypadState};
+use super::io::serial::{Disconnected, SerialPeer};
+use super::sgb::{SGB, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
 use super::{bus::Bus, cpu::CPU, instructions::instructions::Instruction, utils::*};
 use crate::gb::io::io_registers::IORegisters;
-use crate::RenderBuffer;
+
+/// The number of m-cycles the DMG takes to draw a single frame (70224 t-cycles).
+pub const M_CYCLES_PER_FRAME: usize = 17556;
+
+/// The number of m-cycles (1 second) after the last write to battery-backed RAM before it is flushed to disk.
+pub const SAVE_FLUSH_DELAY: u32 = 1048576;
+
+/// Something that happened inside the emulator which a frontend may want to react to, see [`GameboyEmulator::poll_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    /// A rumble cart's motor was turned on (`true`) or off (`false`).
+    Rumble(bool),
+    /// The CPU executed an invalid `opcode` at `pc` and has locked up, see [`CPUState::Locked`].
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// The cartridge's `.sav` file couldn't be read when the emulator was created, so it starts with blank RAM.
+    SaveLoadFailed(std::io::ErrorKind),
+    /// Battery-backed RAM couldn't be written to the cartridge's `.sav` file.
+    SaveFailed(std::io::ErrorKind),
+    /// A [`crate::gb::io::link::SocketLink`] lost its connection, and now behaves as if nothing is connected.
+    LinkDisconnected(std::io::ErrorKind),
+    /// A [`crate::gb::io::printer::GameBoyPrinter`] print couldn't be saved to its output directory, it is still kept in memory.
+    PrintSaveFailed(std::io::ErrorKind),
+}
 
 #[derive(Debug)]
 pub struct GameboyEmulator {
     pub prev_update: Instant,
+    pub model: Model,
+    /// Runs CGB software on a CGB model as the DMG compatibility mode would, with [`PPU::palette`] instead of color palettes.
+    pub force_dmg: bool,
     pub cpu: CPU,
     pub ppu: PPU,
+    pub apu: APU,
     pub ime: IME,
     pub bus: Bus,
-    pub is_halted: bool,
+    pub cpu_state: CPUState,
+    /// Set by `HALT` when it exits immediately with `IME` disabled, the next opcode fetch does not increment `PC`.
+    pub halt_bug: bool,
     pub io_registers: IORegisters,
+    /// The Super Game Boy's state, only when running as an SGB.
+    pub sgb: Option<SGB>,
+    /// Whatever is plugged into the link port, see [`GameboyEmulator::connect_serial`].
+    pub serial_peer: Box<dyn SerialPeer>,
     pub current_instruction: Instruction,
+    pub events: VecDeque<EmulatorEvent>,
+    /// `Some(m-cycles)` left until battery-backed RAM is flushed, reset by every write to it.
+    pub save_flush_timer: Option<u32>,
 }
 
 impl GameboyEmulator {
-    pub fn update(
-        &mut self,
-        input: &mut WinitInputHelper,
-        _window: Rc<Window>,
-        _buffer: &mut RenderBuffer,
-    ) {
+    /// Creates a DMG which has already finished booting, see [`GameboyEmulator::new_with_model`].
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self::new_with_model(cartridge, Model::DMG, None)
+    }
+
+    /// Creates a `model` which runs `boot_rom` first if there is one, otherwise starting at `0x0100` as if it had already booted.
+    ///
+    /// A `.sav` file which can't be read is reported as [`EmulatorEvent::SaveLoadFailed`].
+    pub fn new_with_model(cartridge: Cartridge, model: Model, boot_rom: Option<Vec<u8>>) -> Self {
+        let has_boot_rom = boot_rom.is_some();
+        let sgb = (model == Model::SGB).then(|| SGB::new(&cartridge));
+        let mut emu = Self {
+            prev_update: Instant::now(),
+            model,
+            force_dmg: false,
+            cpu: CPU::new_init(),
+            ppu: PPU::new_init(),
+            apu: APU::new(),
+            ime: IME::Disabled,
+            cpu_state: CPUState::Running,
+            halt_bug: false,
+            bus: Bus {
+                mbc: cartridge.get_mbc(),
+                cartridge,
+                boot_rom,
+                vram: VRAM::new_empty(),
+                wram: WRAM::new_empty(),
+                oam: OAM::new_empty(),
+                hram: HRAM::new_empty(),
+                #[cfg(test)]
+                test_ram: None,
+            },
+            io_registers: IORegisters::new(),
+            sgb,
+            serial_peer: Box::new(Disconnected),
+            current_instruction: Instruction::default(),
+            events: VecDeque::new(),
+            save_flush_timer: None,
+        };
+        if !has_boot_rom {
+            emu.skip_boot_rom();
+        }
+        if let Err(err) = emu.load_save() {
+            emu.push_event(EmulatorEvent::SaveLoadFailed(err.kind()));
+        }
+        emu
+    }
+
+    /// Loads battery-backed RAM from the cartridge's `.sav` file, if it has one.
+    pub fn load_save(&mut self) -> std::io::Result<()> {
+        if let Some(path) = &self.bus.cartridge.save_path {
+            match std::fs::read(path) {
+                Ok(data) => self.bus.mbc.load_save_data(&data),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes battery-backed RAM to the cartridge's `.sav` file, if it has one.
+    pub fn flush_save(&mut self) -> std::io::Result<()> {
+        self.save_flush_timer = None;
+        if let Some(path) = &self.bus.cartridge.save_path {
+            std::fs::write(path, self.bus.mbc.get_save_data())?;
+        }
+        Ok(())
+    }
+
+    /// Plugs `peer` into the link port, replacing whatever was connected before.
+    pub fn connect_serial(&mut self, peer: impl SerialPeer + 'static) {
+        self.serial_peer = Box::new(peer);
+    }
+
+    /// Queues `event` for the frontend, replacing any rumble event that has not been polled yet
+    /// so that only the motor's latest state is kept.
+    pub fn push_event(&mut self, event: EmulatorEvent) {
+        if let EmulatorEvent::Rumble(_) = event {
+            self.events
+                .retain(|event| !matches!(event, EmulatorEvent::Rumble(_)));
+        }
+        self.events.push_back(event);
+    }
+
+    /// Returns the oldest event that has not been polled yet.
+    #[inline]
+    pub fn poll_event(&mut self) -> Option<EmulatorEvent> {
+        self.events.pop_front()
+    }
+
+    /// Runs the emulator for a single frame's worth of m-cycles, holding `joypad` for the whole frame.
+    ///
+    /// A frame takes twice as many m-cycles in double speed mode, as the PPU keeps running at the normal speed.
+    pub fn run_frame(&mut self, joypad: JoypadState) {
+        // ? Counted in half m-cycles, as the speed can be switched part way through the frame.
+        let mut half_cycles = 0;
+        while half_cycles < M_CYCLES_PER_FRAME * 2 {
+            half_cycles += match self.is_double_speed() {
+                true => 1,
+                false => 2,
+            };
+            self.update(joypad);
+        }
+    }
+
+    /// Returns the audio samples generated since the last call, see [`APU::sample_rate`].
+    #[inline]
+    pub fn take_samples(&mut self) -> Vec<StereoSample> {
+        std::mem::take(&mut self.apu.samples)
+    }
+
+    /// Returns the per-channel audio samples generated since the last call, see [`APU::capture_channels`].
+    #[inline]
+    pub fn take_channel_samples(&mut self) -> Vec<[f32; 4]> {
+        std::mem::take(&mut self.apu.channel_samples)
+    }
+
+    /// Returns the current LCD output, see [`PPU::frame_buffer`].
+    #[inline]
+    pub fn frame_buffer(&self) -> &[u32] {
+        &self.ppu.frame_buffer
+    }
+
+    /// Returns what should be displayed along with its width and height,
+    /// the SGB's output with its border (see [`SGB::frame_buffer`]) or otherwise the LCD output.
+    pub fn display(&self) -> (&[u32], usize, usize) {
+        match &self.sgb {
+            Some(sgb) => (&sgb.frame_buffer, SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT),
+            None => (&self.ppu.frame_buffer, SCREEN_WIDTH, SCREEN_HEIGHT),
+        }
+    }
+
+    /// Updates the emulator as if 4 t-cycles (1 m-cycle) have passed.
+    pub fn update(&mut self, joypad: JoypadState) {
         // ? Get, wait and update the time between m-cycles.
         // let prev_update = std::mem::replace(&mut self.prev_update, Instant::now());
         // let delta_time = self.prev_update.duration_since(prev_update);
@@ -37,53 +205,104 @@ impl GameboyEmulator {
         //     continue;
         // }
 
-        if self.is_halted {
-            return;
+        // ? In double speed mode, everything but the CPU, the timer and the serial port runs every other m-cycle.
+        let single_speed_cycle =
+            !self.is_double_speed() || self.io_registers.timer.DIV & 0b100 == 0;
+
+        // ? Cartridge hardware (e.g. the MBC3 clock) has its own clock, and keeps running even in STOP mode.
+        if single_speed_cycle {
+            self.bus.mbc.update();
         }
 
-        IORegisters::update(self, input);
+        // ? Flush battery-backed RAM once writes to it have gone quiet.
+        if let Some(cycles) = self.save_flush_timer {
+            match cycles {
+                0 => {
+                    if let Err(err) = self.flush_save() {
+                        self.push_event(EmulatorEvent::SaveFailed(err.kind()));
+                    }
+                }
+                _ => self.save_flush_timer = Some(cycles - 1),
+            }
+        }
+
+        // ? STOP mode ends once any selected joypad line goes low, regardless of `IE`.
+        if self.cpu_state == CPUState::Stopped {
+            JoypadRegisters::update(self, joypad);
+            if self.io_registers.joypad.input_state & 0xF == 0xF {
+                return;
+            }
+            self.cpu_state = CPUState::Running;
+        }
 
-        // ? Update IME state if `EI` was called.
-        if self.ime == IME::Scheduled {
-            self.ime = IME::Enabled;
+        // ? The PPU and the APU keep running regardless of the CPU.
+        if single_speed_cycle {
+            PPU::render_step(self);
+            APU::update(self);
+        }
+
+        IORegisters::update(self, joypad);
+
+        match self.cpu_state {
+            // ? The CPU wakes from `HALT` as soon as an interrupt is pending, even if `IME` is disabled (in which case it isn't serviced).
+            CPUState::Halted if self.get_pending_interrupts() != 0 => {
+                self.cpu_state = CPUState::Running
+            }
+            CPUState::Halted | CPUState::Locked => return,
+            CPUState::Running | CPUState::Stopped => {}
         }
 
         // ? Get the next instruction if the previous instruction has completed.
         if self.current_instruction.has_completed() {
-            // ? Check for any enabled interrupts first.
-            let pc = self.cpu.get_register_pair(RegisterPair::PC);
-            let mut interrupt_occurred  = false;
-            if self.ime == IME::Enabled {
-                let enabled_interrupts = Bus::read(self, 0xFF0F) & Bus::read(self, 0xFFFF);
-                if let Some(current_interrupt) = InterruptMask::get_interrupt_from_register(enabled_interrupts) {
-                    self.ime = IME::Disabled;
-                    self.current_instruction = INTERRUPT(current_interrupt);
-                    self.set_interrupt_flag(current_interrupt, false);
-                    interrupt_occurred = true;
-                } else {
-                    self.current_instruction = self.read_pc().into();
-                }
-            }
-            if !interrupt_occurred {
+            // ? Interrupts are checked between instructions, in place of fetching the next opcode.
+            if self.ime == IME::Enabled && self.get_pending_interrupts() != 0 {
+                self.ime = IME::Disabled;
+                self.current_instruction = INTERRUPT();
+            } else {
                 self.current_instruction = self.read_pc().into();
             }
-            println!("0x{:0>4X} - {}", pc, &self.current_instruction);
+
+            // ? `EI` only takes effect after the instruction following it.
+            if self.ime == IME::Scheduled {
+                self.ime = IME::Enabled;
+            }
         }
 
         // ? Run the current instruction.
         let mut instruction = std::mem::take(&mut self.current_instruction);
         instruction.step(self);
         self.current_instruction = instruction;
+    }
+
+    /// Returns `true` if a CGB is running CGB software, rather than in DMG compatibility mode.
+    #[inline]
+    pub fn is_cgb_mode(&self) -> bool {
+        self.model.is_cgb() && !self.force_dmg && self.io_registers.KEY0 & 0b0100 == 0
+    }
 
-        // TODO: Interrupts, graphics, audio, serial I/O
+    /// Returns `true` if the CPU has switched to double speed mode, see [`IORegisters::KEY1`].
+    #[inline]
+    pub fn is_double_speed(&self) -> bool {
+        get_bit(self.io_registers.KEY1, 0b1000_0000)
+    }
+
+    /// Enters STOP mode, in which the LCD is blank and only the joypad (and cartridge) are updated.
+    pub fn stop(&mut self) {
+        self.cpu_state = CPUState::Stopped;
+        let blank = PPU::get_blank_color(self);
+        self.ppu.frame_buffer.fill(blank);
     }
 
-    /// Read and return a byte from the address of the `PC`, then increment `PC`.
+    /// Read and return a byte from the address of the `PC`, then increment `PC` (unless the HALT bug has been triggered).
     #[inline]
     pub fn read_pc(&mut self) -> u8 {
         let address = self.cpu.get_register_pair(RegisterPair::PC);
-        self.cpu.inc_register_pair(RegisterPair::PC);
-        Bus::read(self, address) 
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.cpu.inc_register_pair(RegisterPair::PC);
+        }
+        Bus::read(self, address)
     }
 
     /// Read and return a byte from the address of the `SP`, then increment `SP`.
@@ -116,10 +335,15 @@ impl GameboyEmulator {
         Bus::write(self, address, value)
     }
 
+    /// Returns the interrupts that are both requested in `IF` and enabled in `IE`.
+    #[inline]
+    pub fn get_pending_interrupts(&self) -> u8 {
+        self.io_registers.interrupts.IF & self.io_registers.interrupts.IE & 0b0001_1111
+    }
+
+    /// Requests (or clears) an interrupt in `IF`, bypassing the bus so it is unaffected by OAM DMA.
     #[inline]
     pub fn set_interrupt_flag(&mut self, interrupt: InterruptMask, state: bool) {
-        let mut interrupts = Bus::read(self, 0xFF0F);
-        set_bit(&mut interrupts, interrupt, state);
-        Bus::write(self, 0xFF0F, interrupts);
+        set_bit(&mut self.io_registers.interrupts.IF, interrupt, state);
     }
 }
@@ -0,0 +1,46 @@
+use super::{
+    emu::{GameboyEmulator, M_CYCLES_PER_FRAME},
+    io::{audio::CPU_CLOCK, joypad::JoypadState},
+    wav::WavSink,
+};
+
+/// How long to run the emulator for without a frontend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunLength {
+    Frames(u64),
+    /// Emulated (not real) time, rounded up to a whole number of frames.
+    Seconds(f64),
+}
+
+impl RunLength {
+    pub fn get_frames(&self) -> u64 {
+        match *self {
+            RunLength::Frames(frames) => frames,
+            RunLength::Seconds(seconds) => {
+                let frames_per_second = CPU_CLOCK as f64 / (M_CYCLES_PER_FRAME * 4) as f64;
+                (seconds * frames_per_second).ceil() as u64
+            }
+        }
+    }
+}
+
+impl GameboyEmulator {
+    /// Runs as fast as possible for `length` holding `joypad`, recording audio to `audio_sink` after every frame.
+    pub fn run_headless(
+        &mut self,
+        length: RunLength,
+        joypad: JoypadState,
+        mut audio_sink: Option<&mut WavSink>,
+    ) -> std::io::Result<()> {
+        if let Some(sink) = &audio_sink {
+            sink.attach(self);
+        }
+        for _ in 0..length.get_frames() {
+            self.run_frame(joypad);
+            if let Some(sink) = audio_sink.as_deref_mut() {
+                sink.write(self)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::gb::{bus::Bus, emu::GameboyEmulator, utils::*};
+use crate::gb::{bus::Bus, emu::GameboyEmulator, io::io_registers::IORegisters, utils::*};
 
 use super::{operations::*, prefixed_instructions::PREFIX_n8};
 
@@ -117,8 +117,31 @@ impl From<u8> for Instruction {
             }),
             // * 0x1_
             0x10 => Instruction::new("STOP".to_string(), |emu| {
-                emu.is_halted = true;
-                InstructionStep::Complete
+                // ? https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
+                let button_held = emu.io_registers.joypad.input_state & 0xF != 0xF;
+                let interrupt_pending = emu.get_pending_interrupts() != 0;
+                let speed_switch = emu.is_cgb_mode() && get_bit(emu.io_registers.KEY1, 0b1);
+                if !button_held && speed_switch {
+                    // ? An armed speed switch is carried out instead of entering STOP mode.
+                    // ? The CPU really pauses for a while afterwards, which isn't emulated.
+                    IORegisters::write(emu, 0x0004, 0x00);
+                    emu.io_registers.KEY1 = (emu.io_registers.KEY1 ^ 0b1000_0000) & 0b1000_0000;
+                } else if !button_held {
+                    IORegisters::write(emu, 0x0004, 0x00);
+                    emu.stop();
+                } else if !interrupt_pending {
+                    // ? With a button held STOP doesn't enter STOP mode, instead halting as a 2-byte opcode.
+                    emu.cpu_state = CPUState::Halted;
+                }
+
+                // ? The padding byte is only skipped if no interrupt is pending.
+                if interrupt_pending {
+                    return InstructionStep::Complete;
+                }
+                InstructionStep::new(|emu| {
+                    emu.read_pc();
+                    InstructionStep::Complete
+                })
             }),
             0x11 => LD_r16_n16(RegisterPair::DE),
             0x12 => LD_r16_r8(RegisterPair::DE, Register::A),
@@ -353,7 +376,13 @@ impl From<u8> for Instruction {
             0x74 => LD_r16_r8(RegisterPair::HL, Register::H),
             0x75 => LD_r16_r8(RegisterPair::HL, Register::L),
             0x76 => Instruction::new("HALT".to_string(), |emu| {
-                emu.is_halted = true;
+                // ? The HALT bug: with `IME` disabled and an interrupt already pending, `HALT` exits immediately
+                // ? and the byte after it is read twice.
+                if emu.ime != IME::Enabled && emu.get_pending_interrupts() != 0 {
+                    emu.halt_bug = true;
+                } else {
+                    emu.cpu_state = CPUState::Halted;
+                }
                 InstructionStep::Complete
             }),
             0x77 => LD_r16_r8(RegisterPair::HL, Register::A),
@@ -476,7 +505,7 @@ impl From<u8> for Instruction {
             0xD0 => RET_nc(Flag::C),
             0xD1 => POP_r16(RegisterPair::DE),
             0xD2 => JP_nc_n16(Flag::C),
-            0xD3 => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xD3 => INVALID(value),
             0xD4 => CALL_nc_n16(Flag::C),
             0xD5 => PUSH_r16(RegisterPair::DE),
             0xD6 => Instruction::new("SUB A, n8".to_string(), move |_emu| {
@@ -506,9 +535,9 @@ impl From<u8> for Instruction {
                 })
             }),
             0xDA => JP_c_n16(Flag::C),
-            0xDB => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xDB => INVALID(value),
             0xDC => CALL_c_n16(Flag::C),
-            0xDD => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xDD => INVALID(value),
             0xDE => Instruction::new("SBC A, n8".to_string(), move |_emu| {
                 // ? One bus read or write per m-cycle.
                 InstructionStep::new(move |emu| {
@@ -542,8 +571,8 @@ impl From<u8> for Instruction {
                     InstructionStep::Complete
                 })
             }),
-            0xE3 => unimplemented!("GB - {:#X} is and invalid opcode!", value),
-            0xE4 => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xE3 => INVALID(value),
+            0xE4 => INVALID(value),
             0xE5 => PUSH_r16(RegisterPair::HL),
             0xE6 => Instruction::new("AND A, n8".to_string(), |_emu| {
                 // ? One bus read or write per m-cycle.
@@ -597,9 +626,9 @@ impl From<u8> for Instruction {
                     })
                 })
             }),
-            0xEB => unimplemented!("GB - {:#X} is and invalid opcode!", value),
-            0xEC => unimplemented!("GB - {:#X} is and invalid opcode!", value),
-            0xED => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xEB => INVALID(value),
+            0xEC => INVALID(value),
+            0xED => INVALID(value),
             0xEE => Instruction::new("XOR A, n8".to_string(), |_emu| {
                 // ? One bus read or write per m-cycle.
                 InstructionStep::new(move |emu| {
@@ -636,7 +665,7 @@ impl From<u8> for Instruction {
                 emu.ime = IME::Disabled;
                 InstructionStep::Complete
             }),
-            0xF4 => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xF4 => INVALID(value),
             0xF5 => PUSH_r16(RegisterPair::AF),
             0xF6 => Instruction::new("OR A, u8".to_string(), |_emu| {
                 // ? One bus read or write per m-cycle.
@@ -694,8 +723,8 @@ impl From<u8> for Instruction {
                 emu.ime = IME::Scheduled;
                 InstructionStep::Complete
             }),
-            0xFC => unimplemented!("GB - {:#X} is and invalid opcode!", value),
-            0xFD => unimplemented!("GB - {:#X} is and invalid opcode!", value),
+            0xFC => INVALID(value),
+            0xFD => INVALID(value),
             0xFE => Instruction::new("CP A, n8".to_string(), |_emu| {
                 // ? One bus read or write per m-cycle.
                 InstructionStep::new(move |emu| {
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod instructions;
 pub mod operations;
 pub mod prefixed_instructions;
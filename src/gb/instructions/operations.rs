@@ -2,7 +2,7 @@
 
 // * LD
 
-use crate::gb::{bus::Bus, utils::*};
+use crate::gb::{bus::Bus, emu::EmulatorEvent, utils::*};
 
 use super::instructions::*;
 
@@ -681,18 +681,28 @@ pub fn POP_r16(r16: RegisterPair) -> Instruction {
 
 // * Interrupt handling
 
-pub fn INTERRUPT(interrupt: InterruptMask) -> Instruction {
-    Instruction::new(format!("INTERRUPT {:?}", interrupt), move |_emu| {
+/// Dispatches the highest priority pending interrupt, taking 5 m-cycles.
+///
+/// The interrupt is only chosen once `PC`'s high byte has been pushed, if that push overwrote `IE`
+/// so that nothing is pending any more, the dispatch is cancelled and jumps to `0x0000` instead.
+pub fn INTERRUPT() -> Instruction {
+    Instruction::new("INTERRUPT".to_string(), |_emu| {
         // ? "2 machine cycles pass while nothing occurs, presumably the CPU is executing NOPs during this time."
-        InstructionStep::new(move |emu| {
-            let (pc_lsb, pc_msb) = split_u16(emu.cpu.get_register_pair(RegisterPair::PC));
-            InstructionStep::new(move |emu| {
+        InstructionStep::new(|_emu| {
+            InstructionStep::new(|emu| {
+                let (pc_lsb, pc_msb) = split_u16(emu.cpu.get_register_pair(RegisterPair::PC));
                 emu.write_sp(pc_msb);
+                let interrupt =
+                    InterruptMask::get_interrupt_from_register(emu.get_pending_interrupts());
+                if let Some(interrupt) = interrupt {
+                    emu.set_interrupt_flag(interrupt, false);
+                }
                 InstructionStep::new(move |emu| {
                     emu.write_sp(pc_lsb);
                     InstructionStep::new(move |emu| {
-                        emu.cpu
-                            .set_register_pair(RegisterPair::PC, interrupt.get_handler_address());
+                        let address =
+                            interrupt.map_or(0x0000, |interrupt| interrupt.get_handler_address());
+                        emu.cpu.set_register_pair(RegisterPair::PC, address);
                         InstructionStep::Complete
                     })
                 })
@@ -700,3 +710,15 @@ pub fn INTERRUPT(interrupt: InterruptMask) -> Instruction {
         })
     })
 }
+
+// * Invalid opcodes
+
+/// One of the 11 unused opcodes, which lock up the CPU until it is reset.
+pub fn INVALID(opcode: u8) -> Instruction {
+    Instruction::new(format!("INVALID {:#04X}", opcode), move |emu| {
+        let pc = emu.cpu.get_register_pair(RegisterPair::PC).wrapping_sub(1);
+        emu.cpu_state = CPUState::Locked;
+        emu.push_event(EmulatorEvent::IllegalOpcode { pc, opcode });
+        InstructionStep::Complete
+    })
+}
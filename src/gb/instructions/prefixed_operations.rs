@@ -245,6 +245,7 @@ pub fn SRL_r16(_emu: &mut GameboyEmulator, r16: RegisterPair) -> InstructionStep
 // * SWAP & BIT
 
 /// Swap the upper and lower 4 bits of register `r8`.
+#[allow(clippy::manual_rotate)]
 pub fn SWAP_r8(emu: &mut GameboyEmulator, r8: Register) -> InstructionStep {
     let v = emu.cpu.get_register(r8);
     let v = (v << 4) | (v >> 4);
@@ -255,6 +256,7 @@ pub fn SWAP_r8(emu: &mut GameboyEmulator, r8: Register) -> InstructionStep {
 }
 
 /// Swap the upper and lower 4 bits of the value at address `r16`.
+#[allow(clippy::manual_rotate)]
 pub fn SWAP_r16(_emu: &mut GameboyEmulator, r16: RegisterPair) -> InstructionStep {
     // ? One bus read or write per m-cycle.
     InstructionStep::new(move |emu| {
@@ -0,0 +1,726 @@
+#![allow(non_snake_case)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::gb::{emu::GameboyEmulator, utils::get_bit};
+
+/// The number of t-cycles per second.
+pub const CPU_CLOCK: u32 = 4194304;
+
+/// The highest supported sample rate, one sample per m-cycle.
+pub const MAX_SAMPLE_RATE: u32 = CPU_CLOCK / 4;
+
+/// The bit of the internal `DIV` counter whose falling edge clocks the frame sequencer (512Hz).
+pub const DIV_APU_MASK: u16 = 0b0001_0000_0000_0000;
+
+/// Waveforms for each duty cycle (12.5%, 25%, 50%, 75%), played from the most significant bit.
+const DUTY_WAVEFORMS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// A single stereo sample, each side in the range `-1.0..=1.0`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StereoSample {
+    pub left: f32,
+    pub right: f32,
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Audio_details.html#length-timer).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LengthCounter {
+    /// The number of length clocks left until the channel is turned off.
+    pub remaining: u16,
+}
+
+impl LengthCounter {
+    /// Loads the length from the value written to `NRx1`.
+    #[inline]
+    pub fn load(&mut self, max: u16, value: u8) {
+        self.remaining = max - value as u16;
+    }
+
+    /// Reloads an expired length on trigger.
+    #[inline]
+    pub fn trigger(&mut self, max: u16) {
+        if self.remaining == 0 {
+            self.remaining = max;
+        }
+    }
+
+    /// Clocks the length, returning `true` if it expired and the channel should be turned off.
+    #[inline]
+    pub fn clock(&mut self, enabled: bool) -> bool {
+        if enabled && self.remaining > 0 {
+            self.remaining -= 1;
+            return self.remaining == 0;
+        }
+        false
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Audio_Registers.html#ff12--nr12-channel-1-volume--envelope).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Envelope {
+    pub volume: u8,
+    pub timer: u8,
+}
+
+impl Envelope {
+    #[inline]
+    pub fn trigger(&mut self, NRx2: u8) {
+        self.volume = NRx2 >> 4;
+        self.timer = NRx2 & 0b0111;
+    }
+
+    pub fn clock(&mut self, NRx2: u8) {
+        let pace = NRx2 & 0b0111;
+        if pace == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = pace;
+            if get_bit(NRx2, 0b1000) {
+                self.volume = (self.volume + 1).min(15);
+            } else {
+                self.volume = self.volume.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Audio_Registers.html#ff10--nr10-channel-1-sweep), channel 1's period sweep.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub timer: u8,
+    pub shadow_period: u16,
+}
+
+impl Sweep {
+    /// Returns the next period, which may overflow past `0x7FF`.
+    #[inline]
+    pub fn calculate(&self, NR10: u8) -> u16 {
+        let delta = self.shadow_period >> (NR10 & 0b0111);
+        match get_bit(NR10, 0b1000) {
+            true => self.shadow_period.wrapping_sub(delta),
+            false => self.shadow_period + delta,
+        }
+    }
+
+    /// Returns the sweep pace, where `0` is treated as `8` for the timer.
+    #[inline]
+    fn get_pace(NR10: u8) -> u8 {
+        match (NR10 >> 4) & 0b0111 {
+            0 => 8,
+            pace => pace,
+        }
+    }
+}
+
+/// Channels 1 and 2, pulse waves with a volume envelope.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SquareChannel {
+    pub enabled: bool,
+    /// `NRx1` - Duty cycle (bits 7-6) and initial length timer (bits 5-0).
+    pub NRx1: u8,
+    /// `NRx2` - Volume and envelope.
+    pub NRx2: u8,
+    /// `NRx3` - Period low.
+    pub NRx3: u8,
+    /// `NRx4` - Trigger, length enable and period high.
+    pub NRx4: u8,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    /// T-cycles until the next duty step.
+    pub timer: u16,
+    pub duty_step: u8,
+}
+
+impl SquareChannel {
+    #[inline]
+    pub fn get_period(&self) -> u16 {
+        (self.NRx3 as u16) | ((self.NRx4 as u16 & 0b0111) << 8)
+    }
+
+    #[inline]
+    pub fn set_period(&mut self, period: u16) {
+        self.NRx3 = period as u8;
+        self.NRx4 = (self.NRx4 & 0b1111_1000) | ((period >> 8) as u8 & 0b0111);
+    }
+
+    #[inline]
+    pub fn dac_enabled(&self) -> bool {
+        self.NRx2 & 0b1111_1000 != 0
+    }
+
+    pub fn write_NRx1(&mut self, value: u8) {
+        self.NRx1 = value;
+        self.length.load(64, value & 0b0011_1111);
+    }
+
+    pub fn write_NRx2(&mut self, value: u8) {
+        self.NRx2 = value;
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_NRx4(&mut self, value: u8) {
+        self.NRx4 = value;
+        if get_bit(value, 0b1000_0000) {
+            self.enabled = self.dac_enabled();
+            self.length.trigger(64);
+            self.envelope.trigger(self.NRx2);
+            self.timer = (2048 - self.get_period()) * 4;
+        }
+    }
+
+    /// Steps the channel by a single t-cycle.
+    #[inline]
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = (2048 - self.get_period()) * 4;
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    /// Returns the channel's digital output (`0..=15`).
+    #[inline]
+    pub fn output(&self) -> u8 {
+        let waveform = DUTY_WAVEFORMS[(self.NRx1 >> 6) as usize];
+        match self.enabled && get_bit(waveform, 0b1000_0000 >> self.duty_step) {
+            true => self.envelope.volume,
+            false => 0,
+        }
+    }
+}
+
+/// Channel 3, plays 32 4-bit samples from wave RAM.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WaveChannel {
+    pub enabled: bool,
+    /// `0xFF1A` - DAC enable (bit 7).
+    pub NR30: u8,
+    /// `0xFF1B` - Initial length timer.
+    pub NR31: u8,
+    /// `0xFF1C` - Output level (bits 6-5).
+    pub NR32: u8,
+    /// `0xFF1D` - Period low.
+    pub NR33: u8,
+    /// `0xFF1E` - Trigger, length enable and period high.
+    pub NR34: u8,
+    /// `0xFF30..=0xFF3F` - Wave pattern RAM, high nibble first.
+    pub wave_ram: [u8; 16],
+    pub length: LengthCounter,
+    /// T-cycles until the next sample is read.
+    pub timer: u16,
+    /// The index of the current sample in wave RAM (`0..32`).
+    pub position: u8,
+    /// The last sample read from wave RAM.
+    pub sample_buffer: u8,
+}
+
+impl WaveChannel {
+    #[inline]
+    pub fn get_period(&self) -> u16 {
+        (self.NR33 as u16) | ((self.NR34 as u16 & 0b0111) << 8)
+    }
+
+    #[inline]
+    pub fn dac_enabled(&self) -> bool {
+        get_bit(self.NR30, 0b1000_0000)
+    }
+
+    pub fn write_NR30(&mut self, value: u8) {
+        self.NR30 = value;
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_NR31(&mut self, value: u8) {
+        self.NR31 = value;
+        self.length.load(256, value);
+    }
+
+    pub fn write_NR34(&mut self, value: u8) {
+        self.NR34 = value;
+        if get_bit(value, 0b1000_0000) {
+            self.enabled = self.dac_enabled();
+            self.length.trigger(256);
+            self.timer = (2048 - self.get_period()) * 2;
+            self.position = 0;
+        }
+    }
+
+    /// Steps the channel by a single t-cycle.
+    #[inline]
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = (2048 - self.get_period()) * 2;
+            self.position = (self.position + 1) % 32;
+            let byte = self.wave_ram[self.position as usize / 2];
+            self.sample_buffer = match self.position % 2 {
+                0 => byte >> 4,
+                _ => byte & 0x0F,
+            };
+        }
+    }
+
+    /// Returns the channel's digital output (`0..=15`).
+    #[inline]
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        match (self.NR32 >> 5) & 0b11 {
+            0b00 => 0,
+            level => self.sample_buffer >> (level - 1),
+        }
+    }
+}
+
+/// Channel 4, pseudo-random noise from a linear-feedback shift register.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+    /// `0xFF20` - Initial length timer (bits 5-0).
+    pub NR41: u8,
+    /// `0xFF21` - Volume and envelope.
+    pub NR42: u8,
+    /// `0xFF22` - Clock shift (bits 7-4), LFSR width (bit 3) and clock divider (bits 2-0).
+    pub NR43: u8,
+    /// `0xFF23` - Trigger and length enable.
+    pub NR44: u8,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    /// T-cycles until the LFSR is next clocked.
+    pub timer: u32,
+    pub lfsr: u16,
+}
+
+impl NoiseChannel {
+    #[inline]
+    pub fn dac_enabled(&self) -> bool {
+        self.NR42 & 0b1111_1000 != 0
+    }
+
+    #[inline]
+    fn get_timer_period(&self) -> u32 {
+        let divider = match self.NR43 & 0b0111 {
+            0 => 8,
+            divider => divider as u32 * 16,
+        };
+        divider << (self.NR43 >> 4)
+    }
+
+    pub fn write_NR41(&mut self, value: u8) {
+        self.NR41 = value;
+        self.length.load(64, value & 0b0011_1111);
+    }
+
+    pub fn write_NR42(&mut self, value: u8) {
+        self.NR42 = value;
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_NR44(&mut self, value: u8) {
+        self.NR44 = value;
+        if get_bit(value, 0b1000_0000) {
+            self.enabled = self.dac_enabled();
+            self.length.trigger(64);
+            self.envelope.trigger(self.NR42);
+            self.timer = self.get_timer_period();
+            self.lfsr = 0x7FFF;
+        }
+    }
+
+    /// Steps the channel by a single t-cycle.
+    #[inline]
+    pub fn step(&mut self) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.get_timer_period();
+            // ? Clock shifts of 14 and 15 stop the LFSR.
+            if self.NR43 >> 4 >= 14 {
+                return;
+            }
+            let xor = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (xor << 14);
+            if get_bit(self.NR43, 0b1000) {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (xor << 6);
+            }
+        }
+    }
+
+    /// Returns the channel's digital output (`0..=15`).
+    #[inline]
+    pub fn output(&self) -> u8 {
+        match self.enabled && self.lfsr & 1 == 0 {
+            true => self.envelope.volume,
+            false => 0,
+        }
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Audio_Registers.html), `0xFF10..=0xFF3F`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AudioRegisters {
+    /// `0xFF10` - Channel 1 sweep.
+    pub NR10: u8,
+    pub channel1: SquareChannel,
+    pub channel2: SquareChannel,
+    pub channel3: WaveChannel,
+    pub channel4: NoiseChannel,
+    /// `0xFF24` - Master volume and VIN panning.
+    pub NR50: u8,
+    /// `0xFF25` - Sound panning, left in the high nibble and right in the low nibble.
+    pub NR51: u8,
+    /// `0xFF26` (bit 7) - Audio master control.
+    pub power: bool,
+    pub sweep: Sweep,
+    /// The current step of the frame sequencer (`0..8`).
+    pub frame_sequencer_step: u8,
+}
+
+impl AudioRegisters {
+    pub fn new() -> Self {
+        Self {
+            NR10: 0x00,
+            channel1: SquareChannel::default(),
+            channel2: SquareChannel::default(),
+            channel3: WaveChannel::default(),
+            channel4: NoiseChannel::default(),
+            NR50: 0x00,
+            NR51: 0x00,
+            power: false,
+            sweep: Sweep::default(),
+            frame_sequencer_step: 0,
+        }
+    }
+
+    /// Reads an audio register or wave RAM, with unused and write-only bits reading as `1`.
+    pub fn read(&self, index: usize) -> u8 {
+        match index {
+            0x0010 => self.NR10 | 0b1000_0000,
+            0x0011 => self.channel1.NRx1 | 0b0011_1111,
+            0x0012 => self.channel1.NRx2,
+            0x0013 => 0xFF,
+            0x0014 => self.channel1.NRx4 | 0b1011_1111,
+            0x0016 => self.channel2.NRx1 | 0b0011_1111,
+            0x0017 => self.channel2.NRx2,
+            0x0018 => 0xFF,
+            0x0019 => self.channel2.NRx4 | 0b1011_1111,
+            0x001A => self.channel3.NR30 | 0b0111_1111,
+            0x001B => 0xFF,
+            0x001C => self.channel3.NR32 | 0b1001_1111,
+            0x001D => 0xFF,
+            0x001E => self.channel3.NR34 | 0b1011_1111,
+            0x0020 => 0xFF,
+            0x0021 => self.channel4.NR42,
+            0x0022 => self.channel4.NR43,
+            0x0023 => self.channel4.NR44 | 0b1011_1111,
+            0x0024 => self.NR50,
+            0x0025 => self.NR51,
+            0x0026 => {
+                let mut value = 0b0111_0000;
+                value |= (self.power as u8) << 7;
+                value |= (self.channel4.enabled as u8) << 3;
+                value |= (self.channel3.enabled as u8) << 2;
+                value |= (self.channel2.enabled as u8) << 1;
+                value |= self.channel1.enabled as u8;
+                value
+            }
+            0x0030..=0x003F => self.channel3.wave_ram[index - 0x0030],
+            _ => panic!("GB - Audio: Index {:X} out of range!", index),
+        }
+    }
+
+    pub fn write(&mut self, index: usize, value: u8) {
+        // ? Registers are read-only while the APU is off, apart from the DMG's length timers.
+        if !self.power && !matches!(index, 0x0026 | 0x0030..=0x003F) {
+            match index {
+                0x0011 => self.channel1.length.load(64, value & 0b0011_1111),
+                0x0016 => self.channel2.length.load(64, value & 0b0011_1111),
+                0x001B => self.channel3.write_NR31(value),
+                0x0020 => self.channel4.write_NR41(value),
+                _ => {}
+            }
+            return;
+        }
+
+        match index {
+            0x0010 => self.NR10 = value,
+            0x0011 => self.channel1.write_NRx1(value),
+            0x0012 => self.channel1.write_NRx2(value),
+            0x0013 => self.channel1.NRx3 = value,
+            0x0014 => {
+                self.channel1.write_NRx4(value);
+                if get_bit(value, 0b1000_0000) {
+                    self.trigger_sweep();
+                }
+            }
+            0x0016 => self.channel2.write_NRx1(value),
+            0x0017 => self.channel2.write_NRx2(value),
+            0x0018 => self.channel2.NRx3 = value,
+            0x0019 => self.channel2.write_NRx4(value),
+            0x001A => self.channel3.write_NR30(value),
+            0x001B => self.channel3.write_NR31(value),
+            0x001C => self.channel3.NR32 = value,
+            0x001D => self.channel3.NR33 = value,
+            0x001E => self.channel3.write_NR34(value),
+            0x0020 => self.channel4.write_NR41(value),
+            0x0021 => self.channel4.write_NR42(value),
+            0x0022 => self.channel4.NR43 = value,
+            0x0023 => self.channel4.write_NR44(value),
+            0x0024 => self.NR50 = value,
+            0x0025 => self.NR51 = value,
+            0x0026 => self.write_NR52(value),
+            0x0030..=0x003F => self.channel3.wave_ram[index - 0x0030] = value,
+            _ => panic!("GB - Audio: Index {:X} out of range!", index),
+        }
+    }
+
+    /// Turning the APU off clears every register (but not wave RAM), turning it on restarts the frame sequencer.
+    fn write_NR52(&mut self, value: u8) {
+        let power = get_bit(value, 0b1000_0000);
+        if self.power && !power {
+            let wave_ram = self.channel3.wave_ram;
+            *self = Self::new();
+            self.channel3.wave_ram = wave_ram;
+        } else if !self.power && power {
+            self.frame_sequencer_step = 0;
+            self.channel1.duty_step = 0;
+            self.channel2.duty_step = 0;
+        }
+        self.power = power;
+    }
+
+    fn trigger_sweep(&mut self) {
+        self.sweep.shadow_period = self.channel1.get_period();
+        self.sweep.timer = Sweep::get_pace(self.NR10);
+        self.sweep.enabled = self.NR10 & 0b0111_0111 != 0;
+        if self.NR10 & 0b0111 != 0 && self.sweep.calculate(self.NR10) > 0x07FF {
+            self.channel1.enabled = false;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        self.sweep.timer = self.sweep.timer.saturating_sub(1);
+        if self.sweep.timer != 0 {
+            return;
+        }
+        self.sweep.timer = Sweep::get_pace(self.NR10);
+        if !self.sweep.enabled || self.NR10 & 0b0111_0000 == 0 {
+            return;
+        }
+
+        let period = self.sweep.calculate(self.NR10);
+        if period > 0x07FF {
+            self.channel1.enabled = false;
+        } else if self.NR10 & 0b0111 != 0 {
+            self.sweep.shadow_period = period;
+            self.channel1.set_period(period);
+            // ? The overflow check runs again with the new period, but the result is not written back.
+            if self.sweep.calculate(self.NR10) > 0x07FF {
+                self.channel1.enabled = false;
+            }
+        }
+    }
+
+    /// [pandocs](https://gbdev.io/pandocs/Audio_details.html#div-apu), clocked on the falling edge of [`DIV_APU_MASK`].
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.power {
+            return;
+        }
+        if matches!(self.frame_sequencer_step, 0 | 2 | 4 | 6) {
+            if self
+                .channel1
+                .length
+                .clock(get_bit(self.channel1.NRx4, 0b0100_0000))
+            {
+                self.channel1.enabled = false;
+            }
+            if self
+                .channel2
+                .length
+                .clock(get_bit(self.channel2.NRx4, 0b0100_0000))
+            {
+                self.channel2.enabled = false;
+            }
+            if self
+                .channel3
+                .length
+                .clock(get_bit(self.channel3.NR34, 0b0100_0000))
+            {
+                self.channel3.enabled = false;
+            }
+            if self
+                .channel4
+                .length
+                .clock(get_bit(self.channel4.NR44, 0b0100_0000))
+            {
+                self.channel4.enabled = false;
+            }
+        }
+        if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+            self.clock_sweep();
+        }
+        if self.frame_sequencer_step == 7 {
+            self.channel1.envelope.clock(self.channel1.NRx2);
+            self.channel2.envelope.clock(self.channel2.NRx2);
+            self.channel4.envelope.clock(self.channel4.NR42);
+        }
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    /// Steps every channel by a single t-cycle.
+    #[inline]
+    pub fn step(&mut self) {
+        if !self.power {
+            return;
+        }
+        self.channel1.step();
+        self.channel2.step();
+        self.channel3.step();
+        self.channel4.step();
+    }
+
+    /// Returns each channel's analog output (`-1.0..=1.0`), or `0.0` while its DAC is off.
+    pub fn get_channel_outputs(&self) -> [f32; 4] {
+        let dac = |enabled: bool, output: u8| match enabled {
+            true => output as f32 / 7.5 - 1.0,
+            false => 0.0,
+        };
+        [
+            dac(self.channel1.dac_enabled(), self.channel1.output()),
+            dac(self.channel2.dac_enabled(), self.channel2.output()),
+            dac(self.channel3.dac_enabled(), self.channel3.output()),
+            dac(self.channel4.dac_enabled(), self.channel4.output()),
+        ]
+    }
+
+    /// Mixes the channels according to `NR51` panning and `NR50` volume.
+    pub fn mix(&self) -> StereoSample {
+        if !self.power {
+            return StereoSample::default();
+        }
+        let outputs = self.get_channel_outputs();
+        let mut sample = StereoSample::default();
+        for (i, output) in outputs.iter().enumerate() {
+            if get_bit(self.NR51, 0b0001_0000 << i) {
+                sample.left += output;
+            }
+            if get_bit(self.NR51, 0b0000_0001 << i) {
+                sample.right += output;
+            }
+        }
+        let left_volume = ((self.NR50 >> 4) & 0b0111) as f32 + 1.0;
+        let right_volume = (self.NR50 & 0b0111) as f32 + 1.0;
+        sample.left *= left_volume / 8.0 / 4.0;
+        sample.right *= right_volume / 8.0 / 4.0;
+        sample
+    }
+}
+
+impl Default for AudioRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns the APU's output into stereo samples at the frontend's sample rate.
+#[derive(Debug)]
+pub struct APU {
+    /// Samples per second, or `None` to not generate samples at all. Clamped to `1..=MAX_SAMPLE_RATE`.
+    pub sample_rate: Option<u32>,
+    /// Whether to also generate [`APU::channel_samples`].
+    pub capture_channels: bool,
+    /// Samples generated since they were last taken with [`GameboyEmulator::take_samples`].
+    pub samples: Vec<StereoSample>,
+    /// Each channel's output before panning and volume, generated alongside `samples` while `capture_channels` is set.
+    pub channel_samples: Vec<[f32; 4]>,
+    /// Accumulates the sample rate each t-cycle, a sample is due once it reaches [`CPU_CLOCK`].
+    sample_clock: u32,
+    /// The sum of each output (left, right, then channels 1-4) and the number of m-cycles mixed since the last sample,
+    /// averaged to reduce aliasing.
+    mix_sum: [f32; 6],
+    mix_count: u32,
+    /// The charge of the high-pass filter capacitors for each output, removing the DC offset of the DACs.
+    capacitors: [f32; 6],
+}
+
+impl APU {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: None,
+            capture_channels: false,
+            samples: Vec::new(),
+            channel_samples: Vec::new(),
+            sample_clock: 0,
+            mix_sum: [0.0; 6],
+            mix_count: 0,
+            capacitors: [0.0; 6],
+        }
+    }
+
+    /// Steps the audio as if 4 t-cycles have passed, generating a sample if one is due.
+    pub fn update(emu: &mut GameboyEmulator) {
+        let audio = &mut emu.io_registers.audio;
+        for _ in 0..4 {
+            audio.step();
+        }
+
+        let Some(sample_rate) = emu.apu.sample_rate else {
+            return;
+        };
+        let sample_rate = sample_rate.clamp(1, MAX_SAMPLE_RATE);
+        let mix = audio.mix();
+        let channels = match emu.apu.capture_channels {
+            true => audio.get_channel_outputs(),
+            false => [0.0; 4],
+        };
+        let apu = &mut emu.apu;
+        let outputs = [mix.left, mix.right].into_iter().chain(channels);
+        for (sum, output) in apu.mix_sum.iter_mut().zip(outputs) {
+            *sum += output;
+        }
+        apu.mix_count += 1;
+
+        apu.sample_clock += sample_rate * 4;
+        if apu.sample_clock < CPU_CLOCK {
+            return;
+        }
+        apu.sample_clock -= CPU_CLOCK;
+
+        // ? [pandocs](https://gbdev.io/pandocs/Audio_details.html#obscure-behavior), high-pass filter.
+        let charge_factor = 0.999958f32.powf(CPU_CLOCK as f32 / sample_rate as f32);
+        let count = apu.mix_count as f32;
+        let outputs: [f32; 6] = std::array::from_fn(|i| {
+            let input = apu.mix_sum[i] / count;
+            let output = input - apu.capacitors[i];
+            apu.capacitors[i] = input - output * charge_factor;
+            output
+        });
+        apu.mix_sum = [0.0; 6];
+        apu.mix_count = 0;
+
+        apu.samples.push(StereoSample {
+            left: outputs[0],
+            right: outputs[1],
+        });
+        if apu.capture_channels {
+            apu.channel_samples
+                .push([outputs[2], outputs[3], outputs[4], outputs[5]]);
+        }
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gb::{emu::GameboyEmulator, utils::get_bit};
+
+use super::graphics::*;
+
+/// A pixel waiting in the object FIFO.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct ObjPixel {
+    /// 2-bit color index, `0` is transparent.
+    pub color: u8,
+    /// The object's attributes (palette and BG priority bits).
+    pub attributes: u8,
+    /// The object's index in OAM, for CGB priority.
+    pub index: u8,
+}
+
+/// A pixel waiting in the BG/window FIFO.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct BgPixel {
+    /// 2-bit color index.
+    pub color: u8,
+    /// The tile's CGB attributes, see [`get_bg_attributes`].
+    pub attributes: u8,
+}
+
+/// The steps of the background/window fetcher, each taking 2 dots (apart from pushing, which waits for the BG FIFO to empty).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FetcherStep {
+    GetTile,
+    GetTileDataLow,
+    GetTileDataHigh,
+    Push,
+}
+
+/// [pandocs](https://gbdev.io/pandocs/pixel_fifo.html), with timings from [GBEDG](https://hacktix.github.io/GBEDG/ppu/).
+///
+/// Emulates mode 3 dot by dot, so that register writes land mid-scanline and mode 3 varies in length.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PixelFIFO {
+    pub bg_fifo: VecDeque<BgPixel>,
+    pub obj_fifo: VecDeque<ObjPixel>,
+    pub fetcher_step: FetcherStep,
+    /// The number of dots spent on the current fetcher step.
+    pub fetcher_dots: u8,
+    /// The tile column being fetched, relative to the start of the BG or window.
+    pub fetcher_x: u8,
+    pub fetching_window: bool,
+    pub tile_id: u8,
+    pub tile_attributes: u8,
+    pub tile_data_low: u8,
+    pub tile_data_high: u8,
+    /// The first fetch of each line is thrown away.
+    pub first_fetch: bool,
+    /// The number of pixels left to discard for `SCX % 8`.
+    pub discard: u8,
+    /// The X coordinate of the next pixel to be sent to the LCD.
+    pub lx: u8,
+    /// Objects found during OAM scan that have not been fetched yet.
+    pub sprites: Vec<Sprite>,
+    /// `Some((object, dots remaining))` while the fetcher is paused to fetch an object.
+    pub sprite_fetch: Option<(Sprite, u8)>,
+    /// Whether the window was drawn this line, so its line counter should increment.
+    pub window_drawn: bool,
+}
+
+impl PixelFIFO {
+    pub fn new() -> Self {
+        Self {
+            bg_fifo: VecDeque::with_capacity(16),
+            obj_fifo: VecDeque::with_capacity(8),
+            fetcher_step: FetcherStep::GetTile,
+            fetcher_dots: 0,
+            fetcher_x: 0,
+            fetching_window: false,
+            tile_id: 0,
+            tile_attributes: 0,
+            tile_data_low: 0,
+            tile_data_high: 0,
+            first_fetch: true,
+            discard: 0,
+            lx: 0,
+            sprites: Vec::new(),
+            sprite_fetch: None,
+            window_drawn: false,
+        }
+    }
+
+    /// Resets the FIFOs and fetcher at the start of mode 3, scanning OAM for this line's objects.
+    pub fn start_line(emu: &mut GameboyEmulator) {
+        let graphics = &emu.io_registers.graphics;
+        if graphics.LY == graphics.WY {
+            emu.ppu.window_y_triggered = true;
+        }
+        let sprites = match get_bit(graphics.LCDC, LCDC_OBJ_ENABLE) {
+            true => Sprite::oam_scan(&emu.bus.oam, graphics.LY, graphics.get_obj_height()),
+            false => Vec::new(),
+        };
+        emu.ppu.fifo = Self {
+            discard: graphics.SCX % 8,
+            sprites,
+            ..Self::new()
+        };
+    }
+
+    /// Runs mode 3 for a single dot, returning `true` once all 160 pixels of the line have been drawn.
+    pub fn dot(emu: &mut GameboyEmulator) -> bool {
+        // ? Pixel output is paused while an object is being fetched.
+        if let Some((sprite, dots)) = emu.ppu.fifo.sprite_fetch {
+            if dots > 1 {
+                emu.ppu.fifo.sprite_fetch = Some((sprite, dots - 1));
+                return false;
+            }
+            emu.ppu.fifo.sprite_fetch = None;
+            Self::fetch_sprite(emu, sprite);
+        }
+
+        if Self::check_sprites(emu) || Self::check_window(emu) {
+            return false;
+        }
+
+        Self::step_fetcher(emu);
+
+        let Some(bg) = emu.ppu.fifo.bg_fifo.pop_front() else {
+            return false;
+        };
+        if emu.ppu.fifo.discard > 0 {
+            emu.ppu.fifo.discard -= 1;
+            return false;
+        }
+        let obj = emu.ppu.fifo.obj_fifo.pop_front().unwrap_or_default();
+        Self::output_pixel(emu, bg, obj);
+
+        emu.ppu.fifo.lx += 1;
+        if emu.ppu.fifo.lx as usize == SCREEN_WIDTH {
+            if emu.ppu.fifo.window_drawn {
+                emu.ppu.window_line += 1;
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Starts fetching an object if one begins at the current pixel, returning `true` if pixel output should pause.
+    fn check_sprites(emu: &mut GameboyEmulator) -> bool {
+        let fifo = &mut emu.ppu.fifo;
+        if fifo.discard > 0 || fifo.bg_fifo.is_empty() || fifo.first_fetch {
+            return false;
+        }
+        // ? Objects are found in OAM order, so ties in X are already ordered by OAM index.
+        let lx = fifo.lx as u16;
+        let Some(i) = fifo
+            .sprites
+            .iter()
+            .position(|sprite| sprite.x as u16 <= lx + 8)
+        else {
+            return false;
+        };
+        let sprite = fifo.sprites.remove(i);
+        if sprite.x == 0 {
+            // ? Entirely off screen to the left, but still counted towards the 10 object limit.
+            return false;
+        }
+        // ? 6 dots to fetch the object, plus however long the BG fetcher needs to finish its current fetch.
+        let bg_penalty = match fifo.fetcher_step {
+            FetcherStep::GetTile => 2 - fifo.fetcher_dots.min(2),
+            FetcherStep::GetTileDataLow => 4 - fifo.fetcher_dots.min(2),
+            _ => 0,
+        };
+        fifo.sprite_fetch = Some((sprite, 6 + bg_penalty));
+        true
+    }
+
+    /// Switches the fetcher over to the window when it is reached, returning `true` if this dot was spent doing so.
+    fn check_window(emu: &mut GameboyEmulator) -> bool {
+        let graphics = &emu.io_registers.graphics;
+        let fifo = &mut emu.ppu.fifo;
+        if fifo.fetching_window
+            || fifo.discard > 0
+            || !get_bit(graphics.LCDC, LCDC_WINDOW_ENABLE)
+            || !emu.ppu.window_y_triggered
+            || graphics.WX > 166
+            || fifo.lx as u16 + 7 < graphics.WX as u16
+        {
+            return false;
+        }
+        fifo.bg_fifo.clear();
+        fifo.fetching_window = true;
+        fifo.window_drawn = true;
+        fifo.fetcher_x = 0;
+        fifo.fetcher_step = FetcherStep::GetTile;
+        fifo.fetcher_dots = 0;
+        true
+    }
+
+    fn step_fetcher(emu: &mut GameboyEmulator) {
+        let cgb_mode = emu.is_cgb_mode();
+        let graphics = &emu.io_registers.graphics;
+        let vram = &emu.bus.vram;
+        let fifo = &mut emu.ppu.fifo;
+
+        let row = match fifo.fetching_window {
+            true => emu.ppu.window_line,
+            false => graphics.LY.wrapping_add(graphics.SCY),
+        };
+
+        fifo.fetcher_dots += 1;
+        match fifo.fetcher_step {
+            FetcherStep::GetTile if fifo.fetcher_dots == 2 => {
+                let (map_bit, x) = match fifo.fetching_window {
+                    true => (LCDC_WINDOW_MAP, fifo.fetcher_x),
+                    false => (
+                        LCDC_BG_MAP,
+                        (graphics.SCX / 8).wrapping_add(fifo.fetcher_x) & 31,
+                    ),
+                };
+                let map: u16 = match get_bit(graphics.LCDC, map_bit) {
+                    true => 0x1C00,
+                    false => 0x1800,
+                };
+                let map_index = (map + (row as u16 / 8) * 32 + x as u16) as usize;
+                fifo.tile_id = vram[map_index];
+                fifo.tile_attributes = get_bg_attributes(vram, map_index, cgb_mode);
+                fifo.fetcher_step = FetcherStep::GetTileDataLow;
+                fifo.fetcher_dots = 0;
+            }
+            FetcherStep::GetTileDataLow if fifo.fetcher_dots == 2 => {
+                let address = graphics.get_bg_tile_address(fifo.tile_id);
+                fifo.tile_data_low =
+                    get_bg_tile_row(vram, address, row % 8, fifo.tile_attributes).0;
+                fifo.fetcher_step = FetcherStep::GetTileDataHigh;
+                fifo.fetcher_dots = 0;
+            }
+            FetcherStep::GetTileDataHigh if fifo.fetcher_dots == 2 => {
+                let address = graphics.get_bg_tile_address(fifo.tile_id);
+                fifo.tile_data_high =
+                    get_bg_tile_row(vram, address, row % 8, fifo.tile_attributes).1;
+                fifo.fetcher_dots = 0;
+                if fifo.first_fetch {
+                    // ? The first fetch of the line is discarded and the fetcher starts over.
+                    fifo.first_fetch = false;
+                    fifo.fetcher_step = FetcherStep::GetTile;
+                } else {
+                    fifo.fetcher_step = FetcherStep::Push;
+                }
+            }
+            FetcherStep::Push => {
+                if fifo.bg_fifo.is_empty() {
+                    let row = (fifo.tile_data_low, fifo.tile_data_high);
+                    let attributes = fifo.tile_attributes;
+                    fifo.bg_fifo.extend((0..8).map(|x| BgPixel {
+                        color: get_tile_pixel(row, x),
+                        attributes,
+                    }));
+                    fifo.fetcher_x = fifo.fetcher_x.wrapping_add(1);
+                    fifo.fetcher_step = FetcherStep::GetTile;
+                }
+                fifo.fetcher_dots = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Fetches an object's tile row and mixes it into the object FIFO.
+    fn fetch_sprite(emu: &mut GameboyEmulator, sprite: Sprite) {
+        let cgb_mode = emu.is_cgb_mode();
+        // ? Unless `OPRI` selects DMG priority, objects earlier in OAM win even if they were fetched later.
+        let oam_priority = cgb_mode && !get_bit(emu.io_registers.graphics.OPRI, 0b1);
+        let graphics = &emu.io_registers.graphics;
+        let row = sprite.get_tile_row(
+            &emu.bus.vram,
+            graphics.LY,
+            graphics.get_obj_height(),
+            cgb_mode,
+        );
+        let fifo = &mut emu.ppu.fifo;
+        // ? Objects partially off the left of the screen have their first pixels cut off.
+        let skip = 8u8.saturating_sub(sprite.x);
+        while fifo.obj_fifo.len() < 8 {
+            fifo.obj_fifo.push_back(ObjPixel::default());
+        }
+        for x in skip..8 {
+            let slot = &mut fifo.obj_fifo[(x - skip) as usize];
+            let color = get_tile_pixel(row, x);
+            // ? DMG: pixels already in the FIFO belong to higher priority objects.
+            if slot.color == 0 || (oam_priority && color != 0 && sprite.index < slot.index) {
+                *slot = ObjPixel {
+                    color,
+                    attributes: sprite.attributes,
+                    index: sprite.index,
+                };
+            }
+        }
+    }
+
+    /// Mixes a BG and object pixel, applying palettes as they are at this dot.
+    fn output_pixel(emu: &mut GameboyEmulator, bg: BgPixel, obj: ObjPixel) {
+        let graphics = &emu.io_registers.graphics;
+        let obj_color = match get_bit(graphics.LCDC, LCDC_OBJ_ENABLE) {
+            true => obj.color,
+            false => 0,
+        };
+        let x = emu.ppu.fifo.lx as usize;
+        let color = PPU::mix_pixel(emu, x, bg.color, bg.attributes, obj_color, obj.attributes);
+        let index = graphics.LY as usize * SCREEN_WIDTH + emu.ppu.fifo.lx as usize;
+        emu.ppu.frame_buffer[index] = color;
+    }
+}
+
+impl Default for PixelFIFO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
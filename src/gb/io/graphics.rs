@@ -1,20 +1,54 @@
 #![allow(non_snake_case)]
 
-use std::rc::Rc;
-
-use winit::window::Window;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     byte_field,
-    gb::{bus::Bus, emu::GameboyEmulator, utils::join_u16},
-    RenderBuffer,
+    gb::{
+        bus::Bus,
+        emu::GameboyEmulator,
+        io::{fifo::PixelFIFO, hdma::HDMARegisters, scanline},
+        sgb::SGB,
+        utils::{get_bit, join_u16, set_bit, InterruptMask},
+    },
 };
 
-// ? Original color scheme
-// ? 0b00: #0f380f
-// ? 0b01: #306230
-// ? 0b10: #8bac0f
-// ? 0b11: #9bbc0f
+/// Width of the LCD in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the LCD in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// The number of dots (t-cycles) in a scanline.
+pub const DOTS_PER_LINE: u16 = 456;
+/// The number of dots spent in mode 2 (OAM scan) at the start of each visible line.
+pub const OAM_SCAN_DOTS: u16 = 80;
+/// The number of dots spent in mode 3 (drawing) by the scanline renderer.
+pub const DRAWING_DOTS: u16 = 172;
+/// The number of scanlines in a frame, including the 10 lines of VBlank.
+pub const LINES_PER_FRAME: u8 = 154;
+
+/// The original DMG's green color scheme, indexed by shade (`0b00` is the lightest, `0b11` the darkest).
+pub const ORIGINAL_PALETTE: [u32; 4] = [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F];
+
+/// The size of each of the CGB's 2 VRAM banks.
+pub const VRAM_BANK_SIZE: usize = 0x2000;
+
+/// `LCDC` bit 0 - BG & window enable (DMG), or BG & window priority over objects (CGB).
+pub const LCDC_BG_ENABLE: u8 = 0b0000_0001;
+/// `LCDC` bit 1 - OBJ enable.
+pub const LCDC_OBJ_ENABLE: u8 = 0b0000_0010;
+/// `LCDC` bit 2 - OBJ size, 8x8 if unset and 8x16 if set.
+pub const LCDC_OBJ_SIZE: u8 = 0b0000_0100;
+/// `LCDC` bit 3 - BG tile map, `0x9800` if unset and `0x9C00` if set.
+pub const LCDC_BG_MAP: u8 = 0b0000_1000;
+/// `LCDC` bit 4 - BG & window tile data, `0x8800` (signed) if unset and `0x8000` (unsigned) if set.
+pub const LCDC_TILE_DATA: u8 = 0b0001_0000;
+/// `LCDC` bit 5 - Window enable.
+pub const LCDC_WINDOW_ENABLE: u8 = 0b0010_0000;
+/// `LCDC` bit 6 - Window tile map, `0x9800` if unset and `0x9C00` if set.
+pub const LCDC_WINDOW_MAP: u8 = 0b0100_0000;
+/// `LCDC` bit 7 - LCD & PPU enable.
+pub const LCDC_LCD_ENABLE: u8 = 0b1000_0000;
 
 byte_field! {
     #[derive(Debug)]
@@ -25,6 +59,37 @@ byte_field! {
     pub background_map_1: 1024,
     /// `0x9C00..=0x9FFF`
     pub background_map_2: 1024,
+    /// `0x8000..=0x97FF` in bank 1 (CGB).
+    pub tile_data_bank_1: 6144,
+    /// `0x9800..=0x9BFF` in bank 1 (CGB), the attributes of each tile in `background_map_1`.
+    pub background_attributes_1: 1024,
+    /// `0x9C00..=0x9FFF` in bank 1 (CGB), the attributes of each tile in `background_map_2`.
+    pub background_attributes_2: 1024,
+}
+
+byte_field! {
+    /// CGB color palette RAM, 8 palettes of 4 little-endian RGB555 colors.
+    #[derive(Debug)]
+    pub PaletteRAM;
+    pub colors: 64,
+}
+
+impl PaletteRAM {
+    /// Returns color `color` of palette `palette` as `0x00RRGGBB`.
+    pub fn get_color(&self, palette: u8, color: u8) -> u32 {
+        let index = (palette as usize & 0b111) * 8 + color as usize * 2;
+        rgb555_to_rgb888(u16::from_le_bytes([self[index], self[index + 1]]))
+    }
+}
+
+/// Converts a CGB/SGB `0bBBBBBGGGGGRRRRR` color to `0x00RRGGBB`.
+pub fn rgb555_to_rgb888(color: u16) -> u32 {
+    // ? Scale each 5-bit component up to 8 bits.
+    let [r, g, b] = [0, 5, 10].map(|shift| {
+        let component = (color as u32 >> shift) & 0x1F;
+        (component << 3) | (component >> 2)
+    });
+    (r << 16) | (g << 8) | b
 }
 
 byte_field! {
@@ -72,11 +137,131 @@ byte_field! {
     pub obj_39: 4,
 }
 
-#[derive(Debug)]
+impl VRAM {
+    /// Returns the low and high bytes of row `row` (`0..=7`) of the tile at `tile_address` in VRAM bank `bank`.
+    #[inline]
+    pub fn get_tile_row(&self, bank: u8, tile_address: u16, row: u8) -> (u8, u8) {
+        let index =
+            bank as usize * VRAM_BANK_SIZE + (tile_address - 0x8000) as usize + row as usize * 2;
+        (self[index], self[index + 1])
+    }
+}
+
+/// The attributes of a BG/window tile in CGB mode, stored in VRAM bank 1 at the same address as its tile ID.
+///
+/// * bits 0-2: Color palette
+/// * bit 3: VRAM bank of the tile data
+/// * bit 5: X flip
+/// * bit 6: Y flip
+/// * bit 7: BG & window colors 1-3 are drawn over objects
+pub fn get_bg_attributes(vram: &VRAM, map_index: usize, cgb_mode: bool) -> u8 {
+    match cgb_mode {
+        true => vram[VRAM_BANK_SIZE + map_index],
+        false => 0x00,
+    }
+}
+
+/// Returns row `row` of the BG/window tile at `tile_address`, applying the bank and flips from its CGB `attributes`.
+pub fn get_bg_tile_row(vram: &VRAM, tile_address: u16, row: u8, attributes: u8) -> (u8, u8) {
+    let bank = get_bit(attributes, 0b0000_1000) as u8;
+    let row = match get_bit(attributes, 0b0100_0000) {
+        true => 7 - row,
+        false => row,
+    };
+    let (lsb, msb) = vram.get_tile_row(bank, tile_address, row);
+    match get_bit(attributes, 0b0010_0000) {
+        true => (lsb.reverse_bits(), msb.reverse_bits()),
+        false => (lsb, msb),
+    }
+}
+
+/// Returns the 2-bit color index of pixel `x` (`0..=7`, left to right) from a tile row.
+#[inline]
+pub fn get_tile_pixel((lsb, msb): (u8, u8), x: u8) -> u8 {
+    let bit = 7 - x;
+    ((msb >> bit) & 0b1) << 1 | ((lsb >> bit) & 0b1)
+}
+
+/// Returns the shade of the 2-bit color index `color` in the DMG palette register `palette`.
+#[inline]
+pub fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+/// An entry in OAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sprite {
+    /// Y position on screen plus 16.
+    pub y: u8,
+    /// X position on screen plus 8.
+    pub x: u8,
+    pub tile: u8,
+    /// * bits 0-2: CGB palette
+    /// * bit 3: CGB VRAM bank
+    /// * bit 4: DMG palette (`OBP0`/`OBP1`)
+    /// * bit 5: X flip
+    /// * bit 6: Y flip
+    /// * bit 7: BG & window colors 1-3 are drawn over this object
+    pub attributes: u8,
+    /// Index into OAM, used as a tie-breaker for priority.
+    pub index: u8,
+}
+
+impl Sprite {
+    pub fn from_oam(oam: &OAM, index: u8) -> Self {
+        let i = index as usize * 4;
+        Self {
+            y: oam[i],
+            x: oam[i + 1],
+            tile: oam[i + 2],
+            attributes: oam[i + 3],
+            index,
+        }
+    }
+
+    /// Returns the tile row of this sprite on scanline `ly`, handling flipping, 8x16 mode and (in CGB mode) banking.
+    pub fn get_tile_row(&self, vram: &VRAM, ly: u8, height: u8, cgb_mode: bool) -> (u8, u8) {
+        let mut row = ly.wrapping_sub(self.y.wrapping_sub(16));
+        if get_bit(self.attributes, 0b0100_0000) {
+            row = height - 1 - row;
+        }
+        let tile = match height {
+            16 => self.tile & 0xFE,
+            _ => self.tile,
+        };
+        let bank = (cgb_mode && get_bit(self.attributes, 0b0000_1000)) as u8;
+        let (mut lsb, mut msb) = vram.get_tile_row(bank, 0x8000 + tile as u16 * 16, row);
+        if get_bit(self.attributes, 0b0010_0000) {
+            lsb = lsb.reverse_bits();
+            msb = msb.reverse_bits();
+        }
+        (lsb, msb)
+    }
+
+    /// Returns the first (up to) 10 objects on scanline `ly`, in the order they're found in OAM.
+    pub fn oam_scan(oam: &OAM, ly: u8, height: u8) -> Vec<Sprite> {
+        (0..40)
+            .map(|i| Self::from_oam(oam, i))
+            .filter(|sprite| {
+                let top = sprite.y as i16 - 16;
+                (top..top + height as i16).contains(&(ly as i16))
+            })
+            .take(10)
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GraphicsRegisters {
     /// `0xFF40` - LCD control.
     pub LCDC: u8,
     /// `0xFF41` - LCD status.
+    /// * bits 0-1: PPU mode (read-only)
+    /// * bit 2: `LY == LYC` (read-only)
+    /// * bit 3: Mode 0 STAT interrupt source
+    /// * bit 4: Mode 1 STAT interrupt source
+    /// * bit 5: Mode 2 STAT interrupt source
+    /// * bit 6: `LY == LYC` STAT interrupt source
     pub STAT: u8,
     /// `0xFF42` - Background vertical scroll.
     pub SCY: u8,
@@ -86,7 +271,7 @@ pub struct GraphicsRegisters {
     pub LY: u8,
     /// `0xFF45` - LCD Y compare.
     pub LYC: u8,
-    /// `0xFF45` - OAM DMA source address.
+    /// `0xFF46` - OAM DMA source address.
     pub DMA: u8,
     /// `0xFF47` -  Background palette.
     pub BGP: u8,
@@ -98,9 +283,27 @@ pub struct GraphicsRegisters {
     pub WY: u8,
     /// `0xFF4B` - Window X coordinate.
     pub WX: u8,
+    /// `0xFF4F` - VRAM bank (CGB).
+    pub VBK: u8,
+    /// `0xFF68` - BG palette index (CGB).
+    /// * bits 0-5: Index into `bg_palette_ram`
+    /// * bit 7: Increment the index after each write to `BCPD`
+    pub BCPS: u8,
+    /// `0xFF6A` - OBJ palette index (CGB), the same layout as `BCPS`.
+    pub OCPS: u8,
+    /// `0xFF6C` - Object priority mode (CGB), by OAM index if bit 0 is unset or by X coordinate if set.
+    pub OPRI: u8,
+    /// Accessed through `0xFF69` (`BCPD`).
+    pub bg_palette_ram: PaletteRAM,
+    /// Accessed through `0xFF6B` (`OCPD`).
+    pub obj_palette_ram: PaletteRAM,
 
     /// `Some(index)` if transfer is in progress, `None` if not.
     pub DMA_transfer_progress: Option<u8>,
+    /// The upper byte of the source address of the transfer in progress, which keeps going until a restarted transfer begins.
+    pub DMA_source: u8,
+    /// `Some(m-cycles)` until a transfer requested by writing to `DMA` begins.
+    pub DMA_start_delay: Option<u8>,
 }
 
 impl GraphicsRegisters {
@@ -118,39 +321,364 @@ impl GraphicsRegisters {
             OBP1: 0x00,
             WY: 0x00,
             WX: 0x00,
+            VBK: 0x00,
+            BCPS: 0x00,
+            OCPS: 0x00,
+            OPRI: 0x00,
+            bg_palette_ram: PaletteRAM::new_empty(),
+            obj_palette_ram: PaletteRAM::new_empty(),
             DMA_transfer_progress: None,
+            DMA_source: 0x00,
+            DMA_start_delay: None,
         }
     }
 
+    /// [pandocs](https://gbdev.io/pandocs/OAM_DMA_Transfer.html), copies a byte of an OAM DMA transfer as if 1 m-cycle has passed.
+    ///
+    /// A transfer starts 1 m-cycle after `DMA` is written, then copies a byte each m-cycle for 160 m-cycles.
     pub fn update(emu: &mut GameboyEmulator) {
+        // ? The byte being transferred during the previous m-cycle lands in OAM now.
         if let Some(index) = emu.io_registers.graphics.DMA_transfer_progress {
-            let value = Bus::read(emu, join_u16(index, emu.io_registers.graphics.DMA));
-            Bus::write(emu, join_u16(index, 0xFE), value);
+            let address = emu.io_registers.graphics.get_DMA_address(index);
+            emu.bus.oam[index as usize] = Bus::read_direct(emu, address);
             emu.io_registers.graphics.DMA_transfer_progress = match index == 0x9F {
                 true => None,
                 false => Some(index + 1),
             };
         }
+
+        let graphics = &mut emu.io_registers.graphics;
+        graphics.DMA_start_delay = match graphics.DMA_start_delay {
+            Some(1) => {
+                // ? Restarting replaces any transfer in progress, which kept going until now.
+                graphics.DMA_source = graphics.DMA;
+                graphics.DMA_transfer_progress = Some(0);
+                None
+            }
+            Some(cycles) => Some(cycles - 1),
+            None => None,
+        };
+    }
+
+    /// Returns the address the OAM DMA transfer reads byte `index` from.
+    #[inline]
+    pub fn get_DMA_address(&self, index: u8) -> u16 {
+        let address = join_u16(index, self.DMA_source);
+        // ? Sources past WRAM read from WRAM again, like echo RAM, rather than OAM and IO.
+        match address {
+            0xE000..=0xFFFF => address - 0x2000,
+            _ => address,
+        }
+    }
+
+    /// Returns the address of the BG/window tile `tile_id`, using the addressing mode from `LCDC`.
+    #[inline]
+    pub fn get_bg_tile_address(&self, tile_id: u8) -> u16 {
+        match get_bit(self.LCDC, LCDC_TILE_DATA) {
+            true => 0x8000 + tile_id as u16 * 16,
+            false => (0x9000 + (tile_id as i8 as i32) * 16) as u16,
+        }
+    }
+
+    /// Returns the height of objects in pixels.
+    #[inline]
+    pub fn get_obj_height(&self) -> u8 {
+        match get_bit(self.LCDC, LCDC_OBJ_SIZE) {
+            true => 16,
+            false => 8,
+        }
+    }
+
+    #[inline]
+    pub fn write_STAT(&mut self, value: u8) {
+        // ? The mode and coincidence bits are read-only.
+        self.STAT = (value & 0b0111_1000) | (self.STAT & 0b0000_0111);
+    }
+
+    #[inline]
+    pub fn read_BCPD(&self) -> u8 {
+        self.bg_palette_ram[(self.BCPS & 0b0011_1111) as usize]
+    }
+
+    pub fn write_BCPD(&mut self, value: u8) {
+        self.bg_palette_ram[(self.BCPS & 0b0011_1111) as usize] = value;
+        Self::increment_palette_index(&mut self.BCPS);
+    }
+
+    #[inline]
+    pub fn read_OCPD(&self) -> u8 {
+        self.obj_palette_ram[(self.OCPS & 0b0011_1111) as usize]
+    }
+
+    pub fn write_OCPD(&mut self, value: u8) {
+        self.obj_palette_ram[(self.OCPS & 0b0011_1111) as usize] = value;
+        Self::increment_palette_index(&mut self.OCPS);
+    }
+
+    /// Increments the index in `BCPS`/`OCPS` if auto-increment is enabled, wrapping within 64 bytes.
+    fn increment_palette_index(index: &mut u8) {
+        if get_bit(*index, 0b1000_0000) {
+            *index = 0b1000_0000 | (index.wrapping_add(1) & 0b0011_1111);
+        }
     }
 
     pub fn write_DMA(emu: &mut GameboyEmulator, value: u8) {
         emu.io_registers.graphics.DMA = value;
-        emu.io_registers.graphics.DMA_transfer_progress = Some(0);
+        // ? Counting the m-cycle of the write itself, which has already passed by the next update.
+        emu.io_registers.graphics.DMA_start_delay = Some(2);
+    }
+}
+
+impl Default for GraphicsRegisters {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[derive(Debug)]
-pub struct PPU {}
+/// [pandocs](https://gbdev.io/pandocs/Rendering.html#ppu-modes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PPUMode {
+    /// Mode 0 - Waiting until the end of the scanline.
+    HBlank,
+    /// Mode 1 - Waiting until the next frame.
+    VBlank,
+    /// Mode 2 - Searching OAM for objects on this line.
+    OAMScan,
+    /// Mode 3 - Sending pixels to the LCD.
+    Drawing,
+}
+
+impl From<PPUMode> for u8 {
+    fn from(value: PPUMode) -> Self {
+        match value {
+            PPUMode::HBlank => 0b00,
+            PPUMode::VBlank => 0b01,
+            PPUMode::OAMScan => 0b10,
+            PPUMode::Drawing => 0b11,
+        }
+    }
+}
+
+/// Which backend draws the pixels during mode 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Renderer {
+    /// Draws each line all at once at the end of a fixed length mode 3, fast but ignores mid-scanline register writes.
+    Scanline,
+    /// Emulates the pixel FIFOs and fetcher dot by dot, for mid-scanline raster effects and a variable length mode 3.
+    PixelFIFO,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PPU {
+    /// The rendered LCD output, `SCREEN_WIDTH * SCREEN_HEIGHT` pixels stored row by row as `0x00RRGGBB`.
+    pub frame_buffer: Vec<u32>,
+    pub mode: PPUMode,
+    /// The number of dots that have passed on the current scanline.
+    pub line_dots: u16,
+    /// The STAT interrupt line, an interrupt is only requested when it goes from low to high ("STAT blocking").
+    pub stat_line: bool,
+    /// The window's internal line counter, only incremented on lines where the window was drawn.
+    pub window_line: u8,
+    /// Set once `LY == WY` during a frame, the window can only be drawn after this.
+    pub window_y_triggered: bool,
+    /// The colors of each shade, `ORIGINAL_PALETTE` by default.
+    pub palette: [u32; 4],
+    /// Can be changed at any time, taking effect from the next scanline.
+    pub renderer: Renderer,
+    /// The renderer drawing the current line, latched from `renderer` when mode 3 starts.
+    pub line_renderer: Renderer,
+    /// Whether the CPU is locked out of VRAM and OAM while the PPU is using them, see [`PPU::can_access`].
+    /// Turning this off helps find code that only works on emulators which don't enforce it.
+    pub access_restrictions: bool,
+    /// State of the `Renderer::PixelFIFO` backend.
+    pub fifo: PixelFIFO,
+}
 
 impl PPU {
     pub fn new_init() -> Self {
-        Self {}
+        Self {
+            frame_buffer: vec![0x00FFFFFF; SCREEN_WIDTH * SCREEN_HEIGHT],
+            mode: PPUMode::HBlank,
+            line_dots: 0,
+            stat_line: false,
+            window_line: 0,
+            window_y_triggered: false,
+            palette: ORIGINAL_PALETTE,
+            renderer: Renderer::Scanline,
+            line_renderer: Renderer::Scanline,
+            access_restrictions: true,
+            fifo: PixelFIFO::new(),
+        }
     }
 
     /// Step the rendering process as if 4 t-cycles have passed.
-    pub fn render_step(emu: &mut GameboyEmulator, buffer: &mut RenderBuffer) {
+    pub fn render_step(emu: &mut GameboyEmulator) {
         for _ in 0..4 {
-            // TODO: Scanline rendering
+            Self::dot(emu);
+        }
+    }
+
+    /// Step the rendering process by a single dot.
+    fn dot(emu: &mut GameboyEmulator) {
+        if !get_bit(emu.io_registers.graphics.LCDC, LCDC_LCD_ENABLE) {
+            // ? LCD is off: LY is held at 0 and the PPU restarts from the top once turned back on.
+            if emu.ppu.line_dots != 0 || emu.io_registers.graphics.LY != 0 {
+                let blank = Self::get_blank_color(emu);
+                emu.ppu.frame_buffer.fill(blank);
+            }
+            emu.ppu.line_dots = 0;
+            emu.ppu.stat_line = false;
+            emu.ppu.window_line = 0;
+            emu.ppu.window_y_triggered = false;
+            emu.io_registers.graphics.LY = 0;
+            Self::set_mode(emu, PPUMode::HBlank);
+            return;
+        }
+
+        emu.ppu.line_dots += 1;
+        if emu.ppu.line_dots == DOTS_PER_LINE {
+            emu.ppu.line_dots = 0;
+            emu.io_registers.graphics.LY = (emu.io_registers.graphics.LY + 1) % LINES_PER_FRAME;
+            match emu.io_registers.graphics.LY as usize {
+                SCREEN_HEIGHT => {
+                    Self::set_mode(emu, PPUMode::VBlank);
+                    emu.set_interrupt_flag(InterruptMask::VBlank, true);
+                    emu.ppu.window_line = 0;
+                    emu.ppu.window_y_triggered = false;
+                    SGB::compose_frame(emu);
+                }
+                ly if ly < SCREEN_HEIGHT => Self::set_mode(emu, PPUMode::OAMScan),
+                _ => {}
+            }
+        } else if emu.ppu.mode == PPUMode::OAMScan && emu.ppu.line_dots == OAM_SCAN_DOTS {
+            Self::set_mode(emu, PPUMode::Drawing);
+            emu.ppu.line_renderer = emu.ppu.renderer;
+            if emu.ppu.line_renderer == Renderer::PixelFIFO {
+                PixelFIFO::start_line(emu);
+            }
+        } else if emu.ppu.mode == PPUMode::Drawing {
+            let finished = match emu.ppu.line_renderer {
+                Renderer::Scanline => {
+                    let finished = emu.ppu.line_dots >= OAM_SCAN_DOTS + DRAWING_DOTS;
+                    if finished {
+                        scanline::render_scanline(emu);
+                    }
+                    finished
+                }
+                Renderer::PixelFIFO => PixelFIFO::dot(emu),
+            };
+            if finished {
+                Self::set_mode(emu, PPUMode::HBlank);
+                HDMARegisters::hblank(emu);
+            }
+        }
+
+        Self::update_stat(emu);
+    }
+
+    /// [pandocs](https://gbdev.io/pandocs/Accessing_VRAM_and_OAM.html), returns `false` if the CPU can't access `address` in the current mode,
+    /// in which case reads return `0xFF` and writes are ignored.
+    ///
+    /// OAM is in use during modes 2 and 3, and VRAM during mode 3. Both are always accessible while the LCD is off.
+    pub fn can_access(emu: &GameboyEmulator, address: u16) -> bool {
+        if !emu.ppu.access_restrictions || !get_bit(emu.io_registers.graphics.LCDC, LCDC_LCD_ENABLE)
+        {
+            return true;
+        }
+        match address {
+            0x8000..=0x9FFF => emu.ppu.mode != PPUMode::Drawing,
+            0xFE00..=0xFE9F => !matches!(emu.ppu.mode, PPUMode::OAMScan | PPUMode::Drawing),
+            _ => true,
+        }
+    }
+
+    /// Returns the color of the LCD while it is off or in STOP mode.
+    pub fn get_blank_color(emu: &GameboyEmulator) -> u32 {
+        match (emu.is_cgb_mode(), &emu.sgb) {
+            (true, _) => 0x00FFFFFF,
+            (false, Some(sgb)) => rgb555_to_rgb888(sgb.palettes[0][0]),
+            (false, None) => emu.ppu.palette[0],
+        }
+    }
+
+    /// Mixes a BG/window pixel with an object pixel (`obj_color` is `0` if there is none), returning the color sent to the LCD at `x` on this line.
+    ///
+    /// `bg_attributes` are only used in CGB mode, see [`get_bg_attributes`].
+    pub fn mix_pixel(
+        emu: &GameboyEmulator,
+        x: usize,
+        bg_color: u8,
+        bg_attributes: u8,
+        obj_color: u8,
+        obj_attributes: u8,
+    ) -> u32 {
+        let graphics = &emu.io_registers.graphics;
+        let bg_enabled = get_bit(graphics.LCDC, LCDC_BG_ENABLE);
+        if emu.is_cgb_mode() {
+            // ? In CGB mode LCDC bit 0 instead decides whether BG & window priority bits are honoured.
+            let bg_priority =
+                get_bit(obj_attributes, 0b1000_0000) || get_bit(bg_attributes, 0b1000_0000);
+            return match obj_color != 0 && !(bg_enabled && bg_priority && bg_color != 0) {
+                true => graphics
+                    .obj_palette_ram
+                    .get_color(obj_attributes, obj_color),
+                false => graphics.bg_palette_ram.get_color(bg_attributes, bg_color),
+            };
+        }
+
+        // ? If the BG & window are disabled they are drawn as white, and objects are always drawn over them.
+        let bg_color = match bg_enabled {
+            true => bg_color,
+            false => 0,
+        };
+        let shade = match obj_color != 0 && !(get_bit(obj_attributes, 0b1000_0000) && bg_color != 0)
+        {
+            true => {
+                let palette = match get_bit(obj_attributes, 0b0001_0000) {
+                    true => graphics.OBP1,
+                    false => graphics.OBP0,
+                };
+                apply_palette(palette, obj_color)
+            }
+            false if bg_enabled => apply_palette(graphics.BGP, bg_color),
+            false => 0,
+        };
+        match &emu.sgb {
+            Some(sgb) => sgb.get_color(x, graphics.LY as usize, shade),
+            None => emu.ppu.palette[shade as usize],
+        }
+    }
+
+    fn set_mode(emu: &mut GameboyEmulator, mode: PPUMode) {
+        emu.ppu.mode = mode;
+        let graphics = &mut emu.io_registers.graphics;
+        graphics.STAT = (graphics.STAT & !0b0000_0011) | u8::from(mode);
+    }
+
+    /// Updates the `LY == LYC` flag and requests a STAT interrupt on the rising edge of the STAT interrupt line.
+    fn update_stat(emu: &mut GameboyEmulator) {
+        let graphics = &mut emu.io_registers.graphics;
+        let coincidence = graphics.LY == graphics.LYC;
+        set_bit(&mut graphics.STAT, 0b0000_0100, coincidence);
+
+        let stat = graphics.STAT;
+        let stat_line = (coincidence && get_bit(stat, 0b0100_0000))
+            || match emu.ppu.mode {
+                PPUMode::HBlank => get_bit(stat, 0b0000_1000),
+                // ? The mode 2 source also triggers at the start of VBlank.
+                PPUMode::VBlank => {
+                    get_bit(stat, 0b0001_0000)
+                        || (graphics.LY == SCREEN_HEIGHT as u8
+                            && emu.ppu.line_dots == 0
+                            && get_bit(stat, 0b0010_0000))
+                }
+                PPUMode::OAMScan => get_bit(stat, 0b0010_0000),
+                PPUMode::Drawing => false,
+            };
+
+        if stat_line && !emu.ppu.stat_line {
+            emu.set_interrupt_flag(InterruptMask::LCDStat, true);
         }
+        emu.ppu.stat_line = stat_line;
     }
 }
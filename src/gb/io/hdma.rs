@@ -0,0 +1,127 @@
+#![allow(non_snake_case)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::gb::{bus::Bus, emu::GameboyEmulator, utils::*};
+
+use super::graphics::{PPUMode, LCDC_LCD_ENABLE, VRAM_BANK_SIZE};
+
+/// The number of bytes copied at each HBlank.
+pub const HDMA_BLOCK_SIZE: u16 = 16;
+
+/// [pandocs](https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers), CGB VRAM DMA.
+///
+/// Transfers are copied instantly (general purpose) or a block at the start of each HBlank,
+/// without pausing the CPU for the time they would take.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HDMARegisters {
+    /// `0xFF51`/`0xFF52` - Source address, the lower 4 bits are ignored.
+    pub source: u16,
+    /// `0xFF53`/`0xFF54` - Destination address in VRAM, the lower 4 bits are ignored.
+    pub destination: u16,
+    /// `0xFF55` - Transfer length and mode.
+    /// * bits 0-6: The number of 16 byte blocks left, minus 1
+    /// * bit 7: Set if no HBlank transfer is active
+    pub HDMA5: u8,
+    /// Whether an HBlank transfer is active.
+    pub hblank_active: bool,
+}
+
+impl HDMARegisters {
+    pub fn new() -> Self {
+        Self {
+            source: 0x0000,
+            destination: 0x8000,
+            HDMA5: 0xFF,
+            hblank_active: false,
+        }
+    }
+
+    #[inline]
+    pub fn write_HDMA1(&mut self, value: u8) {
+        self.source = join_u16(self.source as u8, value);
+    }
+
+    #[inline]
+    pub fn write_HDMA2(&mut self, value: u8) {
+        self.source = join_u16(value & 0xF0, (self.source >> 8) as u8);
+    }
+
+    #[inline]
+    pub fn write_HDMA3(&mut self, value: u8) {
+        self.destination = join_u16(self.destination as u8, 0x80 | (value & 0x1F));
+    }
+
+    #[inline]
+    pub fn write_HDMA4(&mut self, value: u8) {
+        self.destination = join_u16(value & 0xF0, (self.destination >> 8) as u8);
+    }
+
+    /// Starts a general purpose (bit 7 unset) or HBlank (bit 7 set) transfer, or stops an active HBlank transfer.
+    pub fn write_HDMA5(emu: &mut GameboyEmulator, value: u8) {
+        let hdma = &mut emu.io_registers.hdma;
+        if hdma.hblank_active && !get_bit(value, 0b1000_0000) {
+            hdma.hblank_active = false;
+            hdma.HDMA5 |= 0b1000_0000;
+            return;
+        }
+
+        hdma.HDMA5 = value & 0b0111_1111;
+        if get_bit(value, 0b1000_0000) {
+            hdma.hblank_active = true;
+            // ? A block is copied straight away if the PPU is already in HBlank.
+            let lcd_enabled = get_bit(emu.io_registers.graphics.LCDC, LCDC_LCD_ENABLE);
+            if lcd_enabled && emu.ppu.mode == PPUMode::HBlank {
+                Self::hblank(emu);
+            }
+        } else {
+            while !Self::copy_block(emu) {}
+        }
+    }
+
+    /// Copies the next block of an active HBlank transfer, called at the start of each HBlank.
+    pub fn hblank(emu: &mut GameboyEmulator) {
+        if emu.io_registers.hdma.hblank_active && Self::copy_block(emu) {
+            emu.io_registers.hdma.hblank_active = false;
+        }
+    }
+
+    /// Copies 16 bytes into the current VRAM bank, returning `true` once the transfer has finished.
+    fn copy_block(emu: &mut GameboyEmulator) -> bool {
+        let bank = (emu.io_registers.graphics.VBK & 0b1) as usize;
+        for _ in 0..HDMA_BLOCK_SIZE {
+            let hdma = &emu.io_registers.hdma;
+            let (source, destination) = (hdma.source, hdma.destination);
+            let value = Self::read_source(emu, source);
+            emu.bus.vram[bank * VRAM_BANK_SIZE + (destination & 0x1FFF) as usize] = value;
+
+            let hdma = &mut emu.io_registers.hdma;
+            hdma.source = source.wrapping_add(1);
+            // ? The destination wraps around within VRAM.
+            hdma.destination = 0x8000 | (destination.wrapping_add(1) & 0x1FFF);
+        }
+
+        let hdma = &mut emu.io_registers.hdma;
+        let (blocks, finished) = hdma.HDMA5.overflowing_sub(1);
+        hdma.HDMA5 = match finished {
+            true => 0xFF,
+            false => blocks & 0b0111_1111,
+        };
+        finished
+    }
+
+    /// Reads a source byte as the DMA controller sees it, without OAM DMA conflicts or PPU access restrictions.
+    /// Sources from `0xE000` up read from cartridge RAM at `0xA000..=0xBFFF` instead of echo RAM, OAM or IO.
+    fn read_source(emu: &mut GameboyEmulator, source: u16) -> u8 {
+        match source {
+            0xE000..=0xFFFF => Bus::read_direct(emu, source - 0x4000),
+            _ => Bus::read_direct(emu, source),
+        }
+    }
+}
+
+impl Default for HDMARegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
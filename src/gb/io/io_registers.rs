@@ -1,21 +1,40 @@
 #![allow(non_snake_case)]
 
-use std::io::Write;
+use serde::{Deserialize, Serialize};
 
-use winit_input_helper::WinitInputHelper;
+use crate::gb::{emu::GameboyEmulator, sgb::SGB, utils::*};
 
-use crate::gb::emu::GameboyEmulator;
+use super::{
+    audio::AudioRegisters,
+    graphics::GraphicsRegisters,
+    hdma::HDMARegisters,
+    joypad::{JoypadRegisters, JoypadState},
+    serial::SerialRegisters,
+    timer::TimerRegisters,
+};
 
-use super::{graphics::GraphicsRegisters, joypad::JoypadRegisters, timer::TimerRegisters};
+/// Read from unmapped registers, as nothing drives the data bus. Writes to them are ignored.
+pub const OPEN_BUS: u8 = 0xFF;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct IORegisters {
     pub joypad: JoypadRegisters,
     pub serial: SerialRegisters,
     pub timer: TimerRegisters,
+    pub audio: AudioRegisters,
     pub graphics: GraphicsRegisters,
     pub interrupts: InterruptsRegisters,
+    pub hdma: HDMARegisters,
+    /// `0xFF50` - Unmaps the boot ROM once bit 0 is set, after which it can't be mapped back until reset.
     pub boot_rom_control: u8,
+    /// `0xFF4C` - CGB mode, written by the boot ROM (`0x04` selects DMG compatibility mode).
+    pub KEY0: u8,
+    /// `0xFF4D` - CGB speed switch.
+    /// * bit 0: Switch speed on the next `STOP`
+    /// * bit 7: Current speed, set in double speed mode
+    pub KEY1: u8,
+    /// `0xFF70` - CGB WRAM bank mapped at `0xD000..=0xDFFF`, `0` selects bank 1.
+    pub SVBK: u8,
 }
 
 impl IORegisters {
@@ -24,55 +43,44 @@ impl IORegisters {
             joypad: JoypadRegisters::new(),
             serial: SerialRegisters::new(),
             timer: TimerRegisters::new(),
+            audio: AudioRegisters::new(),
             graphics: GraphicsRegisters::new(),
             interrupts: InterruptsRegisters::new(),
+            hdma: HDMARegisters::new(),
             boot_rom_control: 0x00,
+            KEY0: 0x00,
+            KEY1: 0x00,
+            SVBK: 0x00,
         }
     }
 
     /// Updates timers and I/O as if 4 t-cycles have passed.
-    pub fn update(emu: &mut GameboyEmulator, input: &mut WinitInputHelper) {
-        JoypadRegisters::update(emu, input);
+    pub fn update(emu: &mut GameboyEmulator, joypad: JoypadState) {
+        JoypadRegisters::update(emu, joypad);
+        SerialRegisters::update(emu);
         TimerRegisters::update(emu);
+        GraphicsRegisters::update(emu);
     }
 
     pub fn read(emu: &mut GameboyEmulator, index: usize) -> u8 {
         match index {
             0x0000 => emu.io_registers.joypad.input_state | 0b1100_0000,
-            0x0001 => todo!("GB - IO: Serial byte"),
-            0x0002 => todo!("GB - IO: Serial control"),
-            0x0003 => unimplemented!("GB - IO: Unmapped"),
+            0x0001 => emu.io_registers.serial.SB,
+            0x0002 => emu.io_registers.serial.read_SC(),
+            0x0003 => OPEN_BUS,
             0x0004 => emu.io_registers.timer.read_DIV(),
             0x0005 => emu.io_registers.timer.TIMA,
             0x0006 => emu.io_registers.timer.TMA,
             0x0007 => emu.io_registers.timer.TAC | 0b1111_1000,
-            0x0008..=0x000E => unimplemented!("GB - IO: Unmapped"),
+            0x0008..=0x000E => OPEN_BUS,
             0x000F => emu.io_registers.interrupts.IF | 0b1110_0000,
-            0x0010 => 0xFF, // todo!("GB - IO: Audio channel 1 sweep"),
-            0x0011 => 0xFF, // todo!("GB - IO: Audio channel 1 sound length/wave duty"),
-            0x0012 => 0xFF, // todo!("GB - IO: Audio channel 1 envelope"),
-            0x0013 => 0xFF, // todo!("GB - IO: Audio channel 1 frequency"),
-            0x0014 => 0xFF, // todo!("GB - IO: Audio channel 1 control"),
-            0x0015 => unimplemented!("GB - IO: Unmapped"),
-            0x0016 => 0xFF, // todo!("GB - IO: Audio channel 2 sound length/wave duty"),
-            0x0017 => 0xFF, // todo!("GB - IO: Audio channel 2 envelope"),
-            0x0018 => 0xFF, // todo!("GB - IO: Audio channel 2 frequency"),
-            0x0019 => 0xFF, // todo!("GB - IO: Audio channel 2 control"),
-            0x001A => 0xFF, // todo!("GB - IO: Audio channel 3 enable"),
-            0x001B => 0xFF, // todo!("GB - IO: Audio channel 3 sound length"),
-            0x001C => 0xFF, // todo!("GB - IO: Audio channel 3 volume"),
-            0x001D => 0xFF, // todo!("GB - IO: Audio channel 3 frequency"),
-            0x001E => 0xFF, // todo!("GB - IO: Audio channel 3 control"),
-            0x001F => unimplemented!("GB - IO: Unmapped"),
-            0x0020 => 0xFF, // todo!("GB - IO: Audio channel 4 sound length"),
-            0x0021 => 0xFF, // todo!("GB - IO: Audio channel 4 volume"),
-            0x0022 => 0xFF, // todo!("GB - IO: Audio channel 4 frequency"),
-            0x0023 => 0xFF, // todo!("GB - IO: Audio channel 4 control"),
-            0x0024 => 0xFF, // todo!("GB - IO: Audio output mapping"),
-            0x0025 => 0xFF, // todo!("GB - IO: Audio channel mapping"),
-            0x0026 => 0xFF, // todo!("GB - IO: Audio channel control"),
-            0x0027..=0x002F => unimplemented!("GB - IO: Unmapped"),
-            0x0030..=0x003F => todo!("GB - IO: Wave pattern"),
+            0x0010..=0x0014 => emu.io_registers.audio.read(index),
+            0x0015 => OPEN_BUS,
+            0x0016..=0x001E => emu.io_registers.audio.read(index),
+            0x001F => OPEN_BUS,
+            0x0020..=0x0026 => emu.io_registers.audio.read(index),
+            0x0027..=0x002F => OPEN_BUS,
+            0x0030..=0x003F => emu.io_registers.audio.read(index),
             0x0040 => emu.io_registers.graphics.LCDC,
             0x0041 => emu.io_registers.graphics.STAT | 0b1000_0000,
             0x0042 => emu.io_registers.graphics.SCY,
@@ -85,7 +93,18 @@ impl IORegisters {
             0x0049 => emu.io_registers.graphics.OBP1,
             0x004A => emu.io_registers.graphics.WY,
             0x004B => emu.io_registers.graphics.WX,
-            0x0050 => emu.io_registers.boot_rom_control,
+            0x004D if emu.is_cgb_mode() => emu.io_registers.KEY1 | 0b0111_1110,
+            0x004F if emu.is_cgb_mode() => emu.io_registers.graphics.VBK | 0b1111_1110,
+            0x004C..=0x004F => OPEN_BUS,
+            0x0050 => OPEN_BUS,
+            0x0055 if emu.is_cgb_mode() => emu.io_registers.hdma.HDMA5,
+            0x0068 if emu.is_cgb_mode() => emu.io_registers.graphics.BCPS | 0b0100_0000,
+            0x0069 if emu.is_cgb_mode() => emu.io_registers.graphics.read_BCPD(),
+            0x006A if emu.is_cgb_mode() => emu.io_registers.graphics.OCPS | 0b0100_0000,
+            0x006B if emu.is_cgb_mode() => emu.io_registers.graphics.read_OCPD(),
+            0x006C if emu.is_cgb_mode() => emu.io_registers.graphics.OPRI | 0b1111_1110,
+            0x0070 if emu.is_cgb_mode() => emu.io_registers.SVBK | 0b1111_1000,
+            0x0051..=0x00FE => OPEN_BUS,
             0x00FF => emu.io_registers.interrupts.IE | 0b1110_0000,
             _ => panic!("GB - IO: Index {:X} out of range!", index),
         }
@@ -93,46 +112,37 @@ impl IORegisters {
 
     pub fn write(emu: &mut GameboyEmulator, index: usize, value: u8) {
         match index {
-            0x0000 => emu.io_registers.joypad.write(value),
-            0x0001 => SerialRegisters::write_SB(emu, value),
-            0x0002 => todo!("GB - IO: Serial control"),
-            0x0003 => unimplemented!("GB - IO: Unmapped"),
-            0x0004 => emu.io_registers.timer.write_DIV(),
+            0x0000 => {
+                emu.io_registers.joypad.write(value);
+                SGB::write_joypad(emu, value);
+            }
+            0x0001 => emu.io_registers.serial.SB = value,
+            0x0002 => SerialRegisters::write_SC(emu, value),
+            0x0003 => {}
+            0x0004 => {
+                // ? Resetting DIV is a falling edge for the frame sequencer if its bit was set.
+                if emu.io_registers.timer.DIV & TimerRegisters::get_APU_mask(emu) != 0 {
+                    emu.io_registers.audio.clock_frame_sequencer();
+                }
+                emu.io_registers.timer.write_DIV()
+            }
             0x0005 => emu.io_registers.timer.write_TIMA(value),
             0x0006 => emu.io_registers.timer.TMA = value,
             0x0007 => emu.io_registers.timer.TAC = value,
-            0x0008..=0x000E => unimplemented!("GB - IO: Unmapped"),
+            0x0008..=0x000E => {}
             0x000F => emu.io_registers.interrupts.IF = value,
-            0x0010 => {} // todo!("GB - IO: Audio channel 1 sweep"),
-            0x0011 => {} // todo!("GB - IO: Audio channel 1 sound length/wave duty"),
-            0x0012 => {} // todo!("GB - IO: Audio channel 1 envelope"),
-            0x0013 => {} // todo!("GB - IO: Audio channel 1 frequency"),
-            0x0014 => {} // todo!("GB - IO: Audio channel 1 control"),
-            0x0015 => unimplemented!("GB - IO: Unmapped"),
-            0x0016 => {} // todo!("GB - IO: Audio channel 2 sound length/wave duty"),
-            0x0017 => {} // todo!("GB - IO: Audio channel 2 envelope"),
-            0x0018 => {} // todo!("GB - IO: Audio channel 2 frequency"),
-            0x0019 => {} // todo!("GB - IO: Audio channel 2 control"),
-            0x001A => {} // todo!("GB - IO: Audio channel 3 enable"),
-            0x001B => {} // todo!("GB - IO: Audio channel 3 sound length"),
-            0x001C => {} // todo!("GB - IO: Audio channel 3 volume"),
-            0x001D => {} // todo!("GB - IO: Audio channel 3 frequency"),
-            0x001E => {} // todo!("GB - IO: Audio channel 3 control"),
-            0x001F => unimplemented!("GB - IO: Unmapped"),
-            0x0020 => {} // todo!("GB - IO: Audio channel 4 sound length"),
-            0x0021 => {} // todo!("GB - IO: Audio channel 4 volume"),
-            0x0022 => {} // todo!("GB - IO: Audio channel 4 frequency"),
-            0x0023 => {} // todo!("GB - IO: Audio channel 4 control"),
-            0x0024 => {} // todo!("GB - IO: Audio output mapping"),
-            0x0025 => {} // todo!("GB - IO: Audio channel mapping"),
-            0x0026 => {} // todo!("GB - IO: Audio channel control"),
-            0x0027..=0x002F => unimplemented!("GB - IO: Unmapped"),
-            0x0030..=0x003F => todo!("GB - IO: Wave pattern"),
+            0x0010..=0x0014 => emu.io_registers.audio.write(index, value),
+            0x0015 => {}
+            0x0016..=0x001E => emu.io_registers.audio.write(index, value),
+            0x001F => {}
+            0x0020..=0x0026 => emu.io_registers.audio.write(index, value),
+            0x0027..=0x002F => {}
+            0x0030..=0x003F => emu.io_registers.audio.write(index, value),
             0x0040 => emu.io_registers.graphics.LCDC = value,
-            0x0041 => emu.io_registers.graphics.STAT = value,
+            0x0041 => emu.io_registers.graphics.write_STAT(value),
             0x0042 => emu.io_registers.graphics.SCY = value,
             0x0043 => emu.io_registers.graphics.SCX = value,
-            0x0044 => emu.io_registers.graphics.LY = value,
+            0x0044 => {} // ? LY is read-only.
             0x0045 => emu.io_registers.graphics.LYC = value,
             0x0046 => GraphicsRegisters::write_DMA(emu, value),
             0x0047 => emu.io_registers.graphics.BGP = value,
@@ -140,16 +150,41 @@ impl IORegisters {
             0x0049 => emu.io_registers.graphics.OBP1 = value,
             0x004A => emu.io_registers.graphics.WY = value,
             0x004B => emu.io_registers.graphics.WX = value,
-            0x004C..=0x4F => unimplemented!("GB - IO: Unmapped"),
-            0x0050 => emu.io_registers.boot_rom_control = value,
-            0x0051..=0x00FE => unimplemented!("GB - IO: Unmapped"),
+            // ? KEY0 is locked once the boot ROM has finished.
+            0x004C if emu.model.is_cgb() && emu.io_registers.boot_rom_control == 0x00 => {
+                emu.io_registers.KEY0 = value
+            }
+            0x004D if emu.is_cgb_mode() => {
+                set_bit(&mut emu.io_registers.KEY1, 0b1, get_bit(value, 0b1))
+            }
+            0x004F if emu.is_cgb_mode() => emu.io_registers.graphics.VBK = value & 0b1,
+            0x004C..=0x004F => {}
+            0x0050 => emu.io_registers.boot_rom_control |= value & 0b1,
+            0x0051 if emu.is_cgb_mode() => emu.io_registers.hdma.write_HDMA1(value),
+            0x0052 if emu.is_cgb_mode() => emu.io_registers.hdma.write_HDMA2(value),
+            0x0053 if emu.is_cgb_mode() => emu.io_registers.hdma.write_HDMA3(value),
+            0x0054 if emu.is_cgb_mode() => emu.io_registers.hdma.write_HDMA4(value),
+            0x0055 if emu.is_cgb_mode() => HDMARegisters::write_HDMA5(emu, value),
+            0x0068 if emu.is_cgb_mode() => emu.io_registers.graphics.BCPS = value & 0b1011_1111,
+            0x0069 if emu.is_cgb_mode() => emu.io_registers.graphics.write_BCPD(value),
+            0x006A if emu.is_cgb_mode() => emu.io_registers.graphics.OCPS = value & 0b1011_1111,
+            0x006B if emu.is_cgb_mode() => emu.io_registers.graphics.write_OCPD(value),
+            0x006C if emu.is_cgb_mode() => emu.io_registers.graphics.OPRI = value & 0b1,
+            0x0070 if emu.is_cgb_mode() => emu.io_registers.SVBK = value & 0b111,
+            0x0051..=0x00FE => {}
             0x00FF => emu.io_registers.interrupts.IE = value,
             _ => panic!("GB - IO: Index {:X} out of range!", index),
         }
     }
 }
 
-#[derive(Debug)]
+impl Default for IORegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct InterruptsRegisters {
     /// `0xFF0F` - Interrupts asserted.
     pub IF: u8,
@@ -163,28 +198,8 @@ impl InterruptsRegisters {
     }
 }
 
-#[derive(Debug)]
-pub struct SerialRegisters {
-    /// `0xFF01` - Serial byte.
-    pub SB: u8,
-    /// `0xFF02` - Serial control.
-    pub SC: u8,
-}
-
-impl SerialRegisters {
-    pub fn new() -> Self {
-        Self { SB: 0x00, SC: 0x00 }
-    }
-
-    pub fn write_SB(emu: &mut GameboyEmulator, value: u8) {
-        emu.io_registers.serial.SB = value;
-        // ! Blargg tests output.
-        if let Ok(mut f) = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open("./blargg_output.txt")
-        {
-            f.write(&[value]).unwrap();
-        }
+impl Default for InterruptsRegisters {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,10 +1,9 @@
-use winit::keyboard::KeyCode;
-use winit_input_helper::WinitInputHelper;
+use serde::{Deserialize, Serialize};
 
 use crate::gb::{utils::{get_bit, set_bit, InterruptMask}, emu::GameboyEmulator};
 
 /// Info from the [Open Game Boy Documentation Project](https://mgba-emu.github.io/gbdoc/#mmio-p1).
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct JoypadRegisters {
     /// A `u8` that determines whether each of the player's inputs are currently being pressed (set to 0) or not (set to 1).
     /// * bit 0: A / Right
@@ -14,25 +13,26 @@ pub struct JoypadRegisters {
     /// * bit 4: Directional inputs are read.
     /// * bit 5: Nondirectional inputs are read.
     pub input_state: u8,
-    pub key_binds: KeyBinds,
 }
 
 impl JoypadRegisters {
     pub fn new() -> Self {
         Self {
             input_state: 0b0000_1111,
-            key_binds: KeyBinds::default(),
         }
     }
 
-    pub fn update(emu: &mut GameboyEmulator, input: &mut WinitInputHelper) {
+    pub fn update(emu: &mut GameboyEmulator, joypad: JoypadState) {
         let mut new_state = emu.io_registers.joypad.input_state | 0xF;
 
         if !get_bit(new_state, 0b0001_0000) {
-            new_state &= emu.io_registers.joypad.key_binds.get_directional(input);
+            new_state &= 0xF0 | joypad.get_directional();
         }
         if !get_bit(new_state, 0b0010_0000) {
-            new_state &= emu.io_registers.joypad.key_binds.get_nondirectional(input);
+            new_state &= 0xF0 | joypad.get_nondirectional();
+        }
+        if let Some(sgb) = &emu.sgb {
+            new_state = sgb.get_joypad_state(new_state);
         }
 
         // ? Joypad interrupt if any bits 0 to 3 goes from 1 to 0 (gets activated).
@@ -49,49 +49,44 @@ impl JoypadRegisters {
     }
 }
 
-#[derive(Debug)]
-pub struct KeyBinds {
-    pub button_a: KeyCode,
-    pub button_b: KeyCode,
-    pub button_select: KeyCode,
-    pub button_start: KeyCode,
-
-    pub button_right: KeyCode,
-    pub button_left: KeyCode,
-    pub button_up: KeyCode,
-    pub button_down: KeyCode,
+impl Default for JoypadRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Default for KeyBinds {
-    fn default() -> Self {
-        Self {
-            button_a: KeyCode::KeyQ,
-            button_b: KeyCode::KeyE,
-            button_select: KeyCode::KeyZ,
-            button_start: KeyCode::KeyX,
+/// The player's inputs, `true` if the button is currently being pressed.
+///
+/// Frontends build one of these from whatever input source they use and pass it to [`GameboyEmulator::update`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JoypadState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
 
-            button_right: KeyCode::KeyD,
-            button_left: KeyCode::KeyA,
-            button_up: KeyCode::KeyW,
-            button_down: KeyCode::KeyS,
-        }
-    }
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
 }
 
-impl KeyBinds {
+impl JoypadState {
+    /// Returns the directional inputs as the lower nibble of `P1` (pressed = 0).
     #[inline]
-    pub fn get_directional(&self, input: &mut WinitInputHelper) -> u8 {
-        (input.key_held(self.button_right) as u8) |
-        (input.key_held(self.button_left) as u8) << 1 |
-        (input.key_held(self.button_up) as u8) << 2 |
-        (input.key_held(self.button_down) as u8) << 3
+    pub fn get_directional(&self) -> u8 {
+        !((self.right as u8) |
+        (self.left as u8) << 1 |
+        (self.up as u8) << 2 |
+        (self.down as u8) << 3) & 0xF
     }
 
+    /// Returns the nondirectional inputs as the lower nibble of `P1` (pressed = 0).
     #[inline]
-    pub fn get_nondirectional(&self, input: &mut WinitInputHelper) -> u8 {
-        (input.key_held(self.button_a) as u8) |
-        (input.key_held(self.button_b) as u8) << 1 |
-        (input.key_held(self.button_select) as u8) << 2 |
-        (input.key_held(self.button_start) as u8) << 3
+    pub fn get_nondirectional(&self) -> u8 {
+        !((self.a as u8) |
+        (self.b as u8) << 1 |
+        (self.select as u8) << 2 |
+        (self.start as u8) << 3) & 0xF
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,364 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::gb::{
+    emu::{EmulatorEvent, GameboyEmulator},
+    io::joypad::JoypadState,
+};
+
+use super::serial::SerialPeer;
+
+/// The number of m-cycles an internal clock transfer takes (8 bits at 8192Hz), after which the other side receives its byte.
+pub const TRANSFER_M_CYCLES: u16 = 1024;
+
+/// How often (in m-cycles) a [`SocketLink`] checks for messages, rather than making a syscall every m-cycle.
+const SOCKET_POLL_M_CYCLES: u16 = 256;
+
+/// Both sides of a link cable have the same protocol, whether they are in the same process or not:
+///
+/// - While waiting for an external clock, a side *offers* its `SB` to the other side, withdrawing the offer once it stops waiting.
+/// - A side starting an internal clock transfer takes the other side's offer (or `0xFF` if there is none)
+///   and sends its own byte back, which finishes the other side's transfer.
+#[derive(Debug, Default)]
+struct PortState {
+    /// `SB`, while this side is waiting for an external clock.
+    offered: Option<u8>,
+    /// A byte clocked in by the other side, and the number of m-cycles until the transfer finishes.
+    incoming: Option<(u8, u16)>,
+}
+
+/// A link cable between two emulators in the same process, see [`LinkCable::new`].
+///
+/// Deterministic as long as both emulators are updated in lockstep, see [`run_lockstep`].
+#[derive(Debug)]
+pub struct LinkCable {
+    ports: Arc<Mutex<[PortState; 2]>>,
+    side: usize,
+    /// Whether this side was polled for an external clock since the last update.
+    polled: bool,
+}
+
+impl LinkCable {
+    /// Returns both ends of a new cable, to be connected with [`GameboyEmulator::connect_serial`].
+    pub fn new() -> (Self, Self) {
+        let ports = Arc::new(Mutex::new([PortState::default(), PortState::default()]));
+        (
+            Self {
+                ports: ports.clone(),
+                side: 0,
+                polled: false,
+            },
+            Self {
+                ports,
+                side: 1,
+                polled: false,
+            },
+        )
+    }
+}
+
+impl SerialPeer for LinkCable {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut ports = self.ports.lock().unwrap();
+        let other = &mut ports[1 - self.side];
+        match other.offered.take() {
+            Some(offered) => {
+                other.incoming = Some((byte, TRANSFER_M_CYCLES));
+                offered
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        self.polled = true;
+        let mut ports = self.ports.lock().unwrap();
+        let port = &mut ports[self.side];
+        match port.incoming {
+            Some((incoming, 0)) => {
+                port.incoming = None;
+                port.offered = None;
+                Some(incoming)
+            }
+            // ? Already clocked in, waiting for the transfer to finish.
+            Some(_) => None,
+            None => {
+                port.offered = Some(byte);
+                None
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        let mut ports = self.ports.lock().unwrap();
+        let port = &mut ports[self.side];
+        if !self.polled {
+            port.offered = None;
+        }
+        self.polled = false;
+        if let Some((_, m_cycles)) = &mut port.incoming {
+            *m_cycles = m_cycles.saturating_sub(1);
+        }
+    }
+}
+
+/// Updates two linked emulators one m-cycle at a time, so transfers between them are deterministic.
+pub fn run_lockstep(
+    a: &mut GameboyEmulator,
+    b: &mut GameboyEmulator,
+    m_cycles: usize,
+    joypads: (JoypadState, JoypadState),
+) {
+    for _ in 0..m_cycles {
+        a.update(joypads.0);
+        b.update(joypads.1);
+    }
+}
+
+/// A TCP or Unix socket connection to another emulator.
+#[derive(Debug)]
+pub enum LinkStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl LinkStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            LinkStream::Tcp(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_nonblocking(nonblocking)
+            }
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for LinkStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            LinkStream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for LinkStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            LinkStream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            LinkStream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Messages sent over a [`SocketLink`], each encoded as a tag byte followed by a value byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkMessage {
+    /// The sender is waiting for an external clock with this `SB`, or has stopped waiting.
+    Offer(Option<u8>),
+    /// The sender has clocked a transfer, taking the receiver's offer.
+    Transfer(u8),
+}
+
+impl LinkMessage {
+    fn encode(self) -> [u8; 2] {
+        match self {
+            LinkMessage::Offer(None) => [0x00, 0x00],
+            LinkMessage::Offer(Some(byte)) => [0x01, byte],
+            LinkMessage::Transfer(byte) => [0x02, byte],
+        }
+    }
+
+    fn decode(bytes: [u8; 2]) -> Option<Self> {
+        match bytes[0] {
+            0x00 => Some(LinkMessage::Offer(None)),
+            0x01 => Some(LinkMessage::Offer(Some(bytes[1]))),
+            0x02 => Some(LinkMessage::Transfer(bytes[1])),
+            _ => None,
+        }
+    }
+}
+
+/// A link cable to an emulator in another process, over a TCP or Unix socket.
+///
+/// Uses the same protocol as [`LinkCable`], but each side only sees the other's offer once it arrives,
+/// so transfers are not deterministic.
+#[derive(Debug)]
+pub struct SocketLink {
+    stream: LinkStream,
+    /// Bytes of a partially received message.
+    buffer: Vec<u8>,
+    /// The other side's last offer.
+    remote_offered: Option<u8>,
+    /// This side's last offer, as sent to the other side.
+    offered: Option<u8>,
+    /// A byte clocked in by the other side.
+    incoming: Option<u8>,
+    polled: bool,
+    connected: bool,
+    /// Why the connection was lost, until it is reported as [`EmulatorEvent::LinkDisconnected`].
+    disconnect_error: Option<ErrorKind>,
+    /// M-cycles until messages are next received.
+    poll_timer: u16,
+}
+
+impl SocketLink {
+    pub fn new(stream: LinkStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            buffer: Vec::with_capacity(2),
+            remote_offered: None,
+            offered: None,
+            incoming: None,
+            polled: false,
+            connected: true,
+            disconnect_error: None,
+            poll_timer: 0,
+        })
+    }
+
+    /// Waits for the other emulator to connect over TCP.
+    pub fn listen_tcp(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        Self::new(LinkStream::Tcp(stream))
+    }
+
+    pub fn connect_tcp(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        Self::new(LinkStream::Tcp(TcpStream::connect(address)?))
+    }
+
+    /// Waits for the other emulator to connect to a Unix socket at `path`, which must not exist yet.
+    #[cfg(unix)]
+    pub fn listen_unix(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let (stream, _) = UnixListener::bind(path)?.accept()?;
+        Self::new(LinkStream::Unix(stream))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(LinkStream::Unix(UnixStream::connect(path)?))
+    }
+
+    /// Returns `false` once the other side has disconnected, after which this behaves as if nothing is connected.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn disconnect(&mut self, kind: ErrorKind) {
+        self.connected = false;
+        self.disconnect_error = Some(kind);
+        self.remote_offered = None;
+        self.incoming = None;
+    }
+
+    fn send(&mut self, message: LinkMessage) {
+        if !self.connected {
+            return;
+        }
+        // ? Messages are tiny, so the socket is briefly made blocking rather than buffering partial writes.
+        let result = self
+            .stream
+            .set_nonblocking(false)
+            .and_then(|_| self.stream.write_all(&message.encode()))
+            .and_then(|_| self.stream.set_nonblocking(true));
+        if let Err(err) = result {
+            self.disconnect(err.kind());
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut bytes = [0x00; 64];
+        while self.connected {
+            match self.stream.read(&mut bytes) {
+                Ok(0) => self.disconnect(ErrorKind::UnexpectedEof),
+                Ok(len) => self.buffer.extend_from_slice(&bytes[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => self.disconnect(err.kind()),
+            }
+        }
+
+        let messages = self.buffer.len() / 2;
+        for i in 0..messages {
+            match LinkMessage::decode([self.buffer[i * 2], self.buffer[i * 2 + 1]]) {
+                Some(LinkMessage::Offer(offered)) => self.remote_offered = offered,
+                Some(LinkMessage::Transfer(byte)) => {
+                    if self.offered.is_some() {
+                        self.incoming = Some(byte);
+                    }
+                }
+                // ? The two sides no longer agree on the protocol, so nothing after this can be trusted.
+                None => {
+                    self.disconnect(ErrorKind::InvalidData);
+                    break;
+                }
+            }
+        }
+        self.buffer.drain(..messages * 2);
+    }
+}
+
+impl SerialPeer for SocketLink {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.receive();
+        match self.remote_offered.take() {
+            Some(offered) => {
+                self.send(LinkMessage::Transfer(byte));
+                offered
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        self.polled = true;
+        if let Some(incoming) = self.incoming.take() {
+            self.offered = None;
+            self.send(LinkMessage::Offer(None));
+            return Some(incoming);
+        }
+        if self.offered != Some(byte) {
+            self.offered = Some(byte);
+            self.send(LinkMessage::Offer(Some(byte)));
+        }
+        None
+    }
+
+    fn update(&mut self) {
+        if !self.polled && self.offered.is_some() {
+            self.offered = None;
+            self.send(LinkMessage::Offer(None));
+        }
+        self.polled = false;
+        self.poll_timer = self.poll_timer.saturating_sub(1);
+        if self.poll_timer == 0 {
+            self.poll_timer = SOCKET_POLL_M_CYCLES;
+            self.receive();
+        }
+    }
+
+    fn poll_event(&mut self) -> Option<EmulatorEvent> {
+        self.disconnect_error
+            .take()
+            .map(EmulatorEvent::LinkDisconnected)
+    }
+}
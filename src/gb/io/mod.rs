@@ -2,3 +2,10 @@ pub mod graphics;
 pub mod io_registers;
 pub mod joypad;
 pub mod timer;
+pub mod scanline;
+pub mod fifo;
+pub mod hdma;
+pub mod audio;
+pub mod serial;
+pub mod link;
+pub mod printer;
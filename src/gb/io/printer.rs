@@ -0,0 +1,336 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::gb::emu::EmulatorEvent;
+
+use super::{
+    graphics::{apply_palette, get_tile_pixel, SCREEN_WIDTH},
+    serial::SerialPeer,
+};
+
+/// The printer's RAM, enough for a 160x144 image.
+pub const PRINTER_RAM_SIZE: usize = 0x1680;
+
+/// The gray level of each shade in printed images, from white to black.
+const PRINT_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// The number of status requests a print stays busy for, as games wait for printing to finish.
+const PRINT_BUSY_POLLS: u8 = 4;
+
+/// [pandocs](https://gbdev.io/pandocs/Gameboy_Printer.html#status-byte).
+pub mod status {
+    pub const CHECKSUM_ERROR: u8 = 0b0000_0001;
+    pub const PRINTING: u8 = 0b0000_0010;
+    pub const IMAGE_DATA_FULL: u8 = 0b0000_0100;
+    pub const UNPROCESSED_DATA: u8 = 0b0000_1000;
+    pub const PACKET_ERROR: u8 = 0b0001_0000;
+}
+
+/// Where the printer is within a packet, by the next byte expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    /// The printer answers `0x81` to show it is connected.
+    KeepAlive,
+    Status,
+}
+
+/// A finished print job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    /// Always 160, the width of the paper.
+    pub width: usize,
+    pub height: usize,
+    /// 8-bit grayscale pixels, row by row.
+    pub pixels: Vec<u8>,
+    /// Line feeds before (high nibble) and after (low nibble) the image, each drawn as a blank tile row.
+    pub margins: u8,
+    pub palette: u8,
+    /// Print darkness, `0x00..=0x7F` with `0x40` as the default. Not applied to the image.
+    pub exposure: u8,
+}
+
+impl PrintedImage {
+    /// Encodes the image as a binary PGM.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut data = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        data.extend_from_slice(&self.pixels);
+        data
+    }
+
+    /// Writes the image to `path`, as a PGM if it has a `.pgm` extension or a PNG otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "pgm") {
+            return std::fs::write(path, self.to_pgm());
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(Error::other)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(Error::other)?;
+        writer.finish().map_err(Error::other)
+    }
+}
+
+/// Where printed images are saved, as `print_0000.png`, `print_0001.png`, etc.
+#[derive(Debug, Clone)]
+pub struct PrintOutput {
+    pub directory: PathBuf,
+    /// `"png"` or `"pgm"`.
+    pub extension: &'static str,
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Gameboy_Printer.html), a Game Boy Printer plugged into the link port.
+///
+/// Finished prints are kept in [`GameBoyPrinter::images`] (shared between clones) and optionally saved to disk.
+#[derive(Debug, Clone)]
+pub struct GameBoyPrinter {
+    pub images: Arc<Mutex<Vec<PrintedImage>>>,
+    pub output: Option<PrintOutput>,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    /// The sum of every byte from the command to the end of the data.
+    checksum: u16,
+    received_checksum: u16,
+    /// Decompressed tile data, 20 tiles per row.
+    ram: Vec<u8>,
+    status: u8,
+    busy_polls: u8,
+    /// Why the last print couldn't be saved, until it is reported as [`EmulatorEvent::PrintSaveFailed`].
+    save_error: Option<ErrorKind>,
+}
+
+impl GameBoyPrinter {
+    pub fn new(output: Option<PrintOutput>) -> Self {
+        Self {
+            images: Arc::new(Mutex::new(Vec::new())),
+            output,
+            state: PacketState::Magic1,
+            command: 0x00,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            ram: Vec::with_capacity(PRINTER_RAM_SIZE),
+            status: 0x00,
+            busy_polls: 0,
+            save_error: None,
+        }
+    }
+
+    /// Returns a copy of every image printed so far.
+    pub fn get_images(&self) -> Vec<PrintedImage> {
+        self.images.lock().unwrap().clone()
+    }
+
+    /// Appends data packet bytes to RAM, expanding runs if the packet is compressed.
+    fn receive_data(&mut self) {
+        let mut data = std::mem::take(&mut self.data);
+        if self.compressed {
+            data = decompress(&data);
+        }
+        let space = PRINTER_RAM_SIZE - self.ram.len();
+        self.ram.extend_from_slice(&data[..data.len().min(space)]);
+        self.status |= status::UNPROCESSED_DATA;
+        if self.ram.len() == PRINTER_RAM_SIZE {
+            self.status |= status::IMAGE_DATA_FULL;
+        }
+    }
+
+    fn print(&mut self) {
+        let [_sheets, margins, palette, exposure] =
+            [0, 1, 2, 3].map(|i| *self.data.get(i).unwrap_or(&0));
+        // ? A palette of 0x00 is treated as the default by the printer.
+        let palette = match palette {
+            0x00 => 0b11_10_01_00,
+            palette => palette,
+        };
+
+        let tile_rows = self.ram.len() / (SCREEN_WIDTH / 8 * 16);
+        let (top, bottom) = ((margins >> 4) as usize * 8, (margins & 0x0F) as usize * 8);
+        let height = top + tile_rows * 8 + bottom;
+        let mut pixels = vec![PRINT_SHADES[0]; SCREEN_WIDTH * height];
+        for y in 0..tile_rows * 8 {
+            for x in 0..SCREEN_WIDTH {
+                let tile = (y / 8) * (SCREEN_WIDTH / 8) + x / 8;
+                let address = tile * 16 + (y % 8) * 2;
+                let color = get_tile_pixel((self.ram[address], self.ram[address + 1]), x as u8 % 8);
+                pixels[(top + y) * SCREEN_WIDTH + x] =
+                    PRINT_SHADES[apply_palette(palette, color) as usize];
+            }
+        }
+
+        let image = PrintedImage {
+            width: SCREEN_WIDTH,
+            height,
+            pixels,
+            margins,
+            palette,
+            exposure,
+        };
+        let mut images = self.images.lock().unwrap();
+        if let Some(output) = &self.output {
+            let path =
+                output
+                    .directory
+                    .join(format!("print_{:04}.{}", images.len(), output.extension));
+            if let Err(err) = image.save(&path) {
+                self.save_error = Some(err.kind());
+            }
+        }
+        images.push(image);
+
+        self.ram.clear();
+        self.status &= !(status::UNPROCESSED_DATA | status::IMAGE_DATA_FULL);
+        self.status |= status::PRINTING;
+        self.busy_polls = PRINT_BUSY_POLLS;
+    }
+
+    /// Runs a packet's command once its checksum has been received.
+    fn run_command(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= status::CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !(status::CHECKSUM_ERROR | status::PACKET_ERROR);
+        match self.command {
+            // ? Initialize.
+            0x01 => {
+                self.ram.clear();
+                self.status = 0x00;
+                self.busy_polls = 0;
+            }
+            0x02 => self.print(),
+            // ? An empty data packet marks the end of the image.
+            0x04 => self.receive_data(),
+            // ? Status, which is also how games wait for printing to finish.
+            0x0F => {
+                if self.busy_polls > 0 {
+                    self.busy_polls -= 1;
+                    if self.busy_polls == 0 {
+                        self.status &= !status::PRINTING;
+                    }
+                }
+            }
+            _ => self.status |= status::PACKET_ERROR,
+        }
+    }
+}
+
+impl Default for GameBoyPrinter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl SerialPeer for GameBoyPrinter {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut response = 0x00;
+        self.state = match self.state {
+            PacketState::Magic1 => match byte {
+                0x88 => PacketState::Magic2,
+                _ => PacketState::Magic1,
+            },
+            PacketState::Magic2 => match byte {
+                0x33 => PacketState::Command,
+                0x88 => PacketState::Magic2,
+                _ => PacketState::Magic1,
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0b1 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                match self.length {
+                    0 => PacketState::ChecksumLow,
+                    _ => PacketState::Data,
+                }
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                match self.data.len() == self.length as usize {
+                    true => PacketState::ChecksumLow,
+                    false => PacketState::Data,
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                PacketState::KeepAlive
+            }
+            PacketState::KeepAlive => {
+                response = 0x81;
+                self.run_command();
+                PacketState::Status
+            }
+            PacketState::Status => {
+                response = self.status;
+                PacketState::Magic1
+            }
+        };
+        response
+    }
+
+    fn poll_event(&mut self) -> Option<EmulatorEvent> {
+        self.save_error.take().map(EmulatorEvent::PrintSaveFailed)
+    }
+}
+
+/// Expands the printer's run-length encoding: a control byte with bit 7 set repeats the next byte `(control & 0x7F) + 2` times,
+/// otherwise the next `control + 1` bytes are copied as they are.
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            if let Some(&byte) = data.get(i) {
+                output.resize(output.len() + (control & 0x7F) as usize + 2, byte);
+            }
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    output
+}
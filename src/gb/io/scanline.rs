@@ -0,0 +1,110 @@
+use crate::gb::{emu::GameboyEmulator, utils::get_bit};
+
+use super::graphics::*;
+
+/// Draws the whole of scanline `LY` into the frame buffer at once, using the registers as they are at the end of mode 3.
+pub fn render_scanline(emu: &mut GameboyEmulator) {
+    let graphics = &emu.io_registers.graphics;
+    let vram = &emu.bus.vram;
+    let ly = graphics.LY;
+    if ly as usize >= SCREEN_HEIGHT {
+        return;
+    }
+
+    let cgb_mode = emu.is_cgb_mode();
+    // ? The 2-bit BG/window color index and CGB attributes of each pixel, needed for object priority.
+    let mut bg_colors = [0u8; SCREEN_WIDTH];
+    let mut bg_attributes = [0u8; SCREEN_WIDTH];
+    let mut obj_pixels = [(0u8, 0u8); SCREEN_WIDTH];
+
+    if ly == graphics.WY {
+        emu.ppu.window_y_triggered = true;
+    }
+
+    // ? The BG & window can only be disabled on the DMG, see `PPU::mix_pixel`.
+    if cgb_mode || get_bit(graphics.LCDC, LCDC_BG_ENABLE) {
+        let window_x = graphics.WX as i16 - 7;
+        let draw_window = get_bit(graphics.LCDC, LCDC_WINDOW_ENABLE)
+            && emu.ppu.window_y_triggered
+            && graphics.WX <= 166;
+
+        for x in 0..SCREEN_WIDTH {
+            let in_window = draw_window && x as i16 >= window_x;
+            let (map, map_x, map_y) = match in_window {
+                true => (
+                    match get_bit(graphics.LCDC, LCDC_WINDOW_MAP) {
+                        true => 0x9C00,
+                        false => 0x9800,
+                    },
+                    (x as i16 - window_x) as u8,
+                    emu.ppu.window_line,
+                ),
+                false => (
+                    match get_bit(graphics.LCDC, LCDC_BG_MAP) {
+                        true => 0x9C00,
+                        false => 0x9800,
+                    },
+                    (x as u8).wrapping_add(graphics.SCX),
+                    ly.wrapping_add(graphics.SCY),
+                ),
+            };
+
+            let map_index = (map - 0x8000 + (map_y as u16 / 8) * 32 + map_x as u16 / 8) as usize;
+            let tile_id = vram[map_index];
+            let attributes = get_bg_attributes(vram, map_index, cgb_mode);
+            let row = get_bg_tile_row(
+                vram,
+                graphics.get_bg_tile_address(tile_id),
+                map_y % 8,
+                attributes,
+            );
+            bg_colors[x] = get_tile_pixel(row, map_x % 8);
+            bg_attributes[x] = attributes;
+        }
+
+        if draw_window && window_x < SCREEN_WIDTH as i16 {
+            emu.ppu.window_line += 1;
+        }
+    }
+
+    if get_bit(graphics.LCDC, LCDC_OBJ_ENABLE) {
+        let height = graphics.get_obj_height();
+        let mut sprites = Sprite::oam_scan(&emu.bus.oam, ly, height);
+        // ? DMG priority: the object with the smaller X wins, and ties are broken by OAM order.
+        // ? CGB priority (unless `OPRI` selects DMG priority): OAM order alone.
+        match cgb_mode && !get_bit(graphics.OPRI, 0b1) {
+            true => sprites.sort_by_key(|sprite| sprite.index),
+            false => sprites.sort_by_key(|sprite| (sprite.x, sprite.index)),
+        }
+
+        for (x, obj_pixel) in obj_pixels.iter_mut().enumerate() {
+            let screen_x = x as i16 + 8;
+            let pixel = sprites.iter().find_map(|sprite| {
+                let column = screen_x - sprite.x as i16;
+                if !(0..8).contains(&column) {
+                    return None;
+                }
+                let row = sprite.get_tile_row(vram, ly, height, cgb_mode);
+                let color = get_tile_pixel(row, column as u8);
+                (color != 0).then_some((color, sprite.attributes))
+            });
+            if let Some(pixel) = pixel {
+                *obj_pixel = pixel;
+            }
+        }
+    }
+
+    let colors: [u32; SCREEN_WIDTH] = std::array::from_fn(|x| {
+        let (obj_color, obj_attributes) = obj_pixels[x];
+        PPU::mix_pixel(
+            emu,
+            x,
+            bg_colors[x],
+            bg_attributes[x],
+            obj_color,
+            obj_attributes,
+        )
+    });
+    emu.ppu.frame_buffer[ly as usize * SCREEN_WIDTH..(ly as usize + 1) * SCREEN_WIDTH]
+        .copy_from_slice(&colors);
+}
@@ -0,0 +1,159 @@
+#![allow(non_snake_case)]
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gb::{
+    emu::{EmulatorEvent, GameboyEmulator},
+    utils::*,
+};
+
+/// The bit of the internal `DIV` counter whose falling edge shifts a bit on internal clock (8192Hz).
+pub const DIV_SERIAL_MASK: u16 = 0b0000_0001_0000_0000;
+
+/// Whatever is plugged into the link port, see [`GameboyEmulator::connect_serial`].
+///
+/// Transfers are exchanged a byte at a time: the bits are still shifted into `SB` at the right speed,
+/// but a peer only sees the whole byte.
+pub trait SerialPeer: Debug {
+    /// This Game Boy has started an internal clock transfer of `byte`, returns the byte that will be shifted in.
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// Polled every m-cycle while this Game Boy is waiting for an external clock with `byte` in `SB`,
+    /// returns the byte shifted in once the peer has clocked a whole transfer.
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        let _ = byte;
+        None
+    }
+
+    /// Called once per m-cycle, for peers that need to keep time (e.g. to pace a network connection).
+    fn update(&mut self) {}
+
+    /// Returns the oldest event the peer wants to tell the frontend about, polled after each [`SerialPeer::update`].
+    fn poll_event(&mut self) -> Option<EmulatorEvent> {
+        None
+    }
+}
+
+/// Nothing connected, internal clock transfers shift in `0xFF` and external clock transfers never finish.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Disconnected;
+
+impl SerialPeer for Disconnected {
+    #[inline]
+    fn exchange(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+}
+
+/// Records every byte sent, e.g. the results printed by test ROMs. Clones share the same buffer.
+#[derive(Debug, Default, Clone)]
+pub struct SerialCapture {
+    pub data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns every byte sent so far.
+    pub fn get_data(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+
+    /// Returns every byte sent so far as text, replacing invalid UTF-8.
+    pub fn get_text(&self) -> String {
+        String::from_utf8_lossy(&self.data.lock().unwrap()).into_owned()
+    }
+}
+
+impl SerialPeer for SerialCapture {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.data.lock().unwrap().push(byte);
+        0xFF
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialRegisters {
+    /// `0xFF01` - Serial byte.
+    pub SB: u8,
+    /// `0xFF02` - Serial control.
+    pub SC: u8,
+    /// The number of bits left to shift in an internal clock transfer.
+    pub bits_remaining: u8,
+    /// The byte being shifted into `SB`, as returned by [`SerialPeer::exchange`].
+    pub incoming: u8,
+}
+
+impl SerialRegisters {
+    pub fn new() -> Self {
+        Self {
+            SB: 0x00,
+            SC: 0x00,
+            bits_remaining: 0,
+            incoming: 0xFF,
+        }
+    }
+
+    #[inline]
+    pub fn read_SC(&self) -> u8 {
+        self.SC | 0b0111_1110
+    }
+
+    /// Setting bit 7 starts a transfer, using the internal clock if bit 0 is set or waiting for the peer's clock otherwise.
+    pub fn write_SC(emu: &mut GameboyEmulator, value: u8) {
+        emu.io_registers.serial.SC = value;
+        emu.io_registers.serial.bits_remaining = 0;
+        if get_bit(value, 0b1000_0000) && get_bit(value, 0b0000_0001) {
+            let SB = emu.io_registers.serial.SB;
+            emu.io_registers.serial.incoming = emu.serial_peer.exchange(SB);
+            emu.io_registers.serial.bits_remaining = 8;
+        }
+    }
+
+    /// Shifts a bit on the falling edge of [`DIV_SERIAL_MASK`], during an internal clock transfer.
+    pub fn clock(emu: &mut GameboyEmulator) {
+        let serial = &mut emu.io_registers.serial;
+        if serial.bits_remaining == 0 {
+            return;
+        }
+        serial.bits_remaining -= 1;
+        let bit = (serial.incoming >> serial.bits_remaining) & 1;
+        serial.SB = (serial.SB << 1) | bit;
+        if serial.bits_remaining == 0 {
+            Self::finish_transfer(emu);
+        }
+    }
+
+    /// Updates the peer and finishes external clock transfers as if 4 t-cycles have passed.
+    pub fn update(emu: &mut GameboyEmulator) {
+        emu.serial_peer.update();
+        while let Some(event) = emu.serial_peer.poll_event() {
+            emu.push_event(event);
+        }
+        let SC = emu.io_registers.serial.SC;
+        if get_bit(SC, 0b1000_0000) && !get_bit(SC, 0b0000_0001) {
+            let SB = emu.io_registers.serial.SB;
+            if let Some(incoming) = emu.serial_peer.poll_external(SB) {
+                emu.io_registers.serial.SB = incoming;
+                Self::finish_transfer(emu);
+            }
+        }
+    }
+
+    fn finish_transfer(emu: &mut GameboyEmulator) {
+        set_bit(&mut emu.io_registers.serial.SC, 0b1000_0000, false);
+        emu.set_interrupt_flag(InterruptMask::Serial, true);
+    }
+}
+
+impl Default for SerialRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
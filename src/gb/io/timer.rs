@@ -1,8 +1,15 @@
-#![allow(non_snake_case)]
+#![allow(non_snake_case, clippy::needless_return)]
+
+use serde::{Deserialize, Serialize};
 
 use crate::gb::{bus::Bus, emu::GameboyEmulator, utils::*};
 
-#[derive(Debug, PartialEq)]
+use super::{
+    audio::DIV_APU_MASK,
+    serial::{SerialRegisters, DIV_SERIAL_MASK},
+};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum TIMAOverflowState {
     /// `TIMA` is incrementing as usual.
     NotOverflowed,
@@ -15,7 +22,7 @@ pub enum TIMAOverflowState {
     SettingToTMA,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TimerRegisters {
     /// `0xFF04` - Clock divider.
     pub DIV: u16,
@@ -45,8 +52,17 @@ impl TimerRegisters {
     /// Update the timer as if 4 t-cycles have passed.
     pub fn update(emu: &mut GameboyEmulator) {
         // ? https://hacktix.github.io/GBEDG/timers/#[cfg(test)]imer-operation
+        let apu_mask = Self::get_APU_mask(emu);
         for _ in 0..4 {
-            emu.io_registers.timer.DIV += 1;
+            let prev_DIV = emu.io_registers.timer.DIV;
+            emu.io_registers.timer.DIV = emu.io_registers.timer.DIV.wrapping_add(1);
+            let falling_edges = prev_DIV & !emu.io_registers.timer.DIV;
+            if falling_edges & apu_mask != 0 {
+                emu.io_registers.audio.clock_frame_sequencer();
+            }
+            if falling_edges & DIV_SERIAL_MASK != 0 {
+                SerialRegisters::clock(emu);
+            }
 
             match &mut emu.io_registers.timer.TIMA_overflow_state {
                 TIMAOverflowState::NotOverflowed => {
@@ -60,8 +76,10 @@ impl TimerRegisters {
                     let div_bit = emu.io_registers.timer.DIV & bitmask != 0;
                     let timer_enable = get_bit(emu.io_registers.timer.TAC, 0b0100);
                     let and_result = div_bit & timer_enable;
+                    let prev_and_result =
+                        std::mem::replace(&mut emu.io_registers.timer.prev_and_result, and_result);
 
-                    if emu.io_registers.timer.prev_and_result == true && and_result == false {
+                    if prev_and_result && !and_result {
                         let (tima, tima_overflow) = emu.io_registers.timer.TIMA.overflowing_add(1);
                         emu.io_registers.timer.TIMA = tima;
                         if tima_overflow {
@@ -89,6 +107,16 @@ impl TimerRegisters {
         }
     }
 
+    /// Returns the bit of `DIV` whose falling edge clocks the APU's frame sequencer.
+    #[inline]
+    pub fn get_APU_mask(emu: &GameboyEmulator) -> u16 {
+        // ? The frame sequencer keeps its rate in double speed mode, so is clocked by the next bit up.
+        match emu.is_double_speed() {
+            true => DIV_APU_MASK << 1,
+            false => DIV_APU_MASK,
+        }
+    }
+
     #[inline]
     pub fn read_DIV(&self) -> u8 {
         return split_u16(self.DIV).1;
@@ -108,3 +136,9 @@ impl TimerRegisters {
         }
     }
 }
+
+impl Default for TimerRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
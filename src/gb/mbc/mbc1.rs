@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+use crate::gb::cartridge::Cartridge;
+
+/// [pandocs](https://gbdev.io/pandocs/MBC1.html), with the banking details from [Gekkio's GBCTR](https://gekkio.fi/files/gb-docs/gbctr.pdf).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MBC1 {
+    /// `0x0000..=0x1FFF` - External RAM is only accessible if `0x_A` is written here.
+    pub ram_enabled: bool,
+    /// `0x2000..=0x3FFF` - 5-bit ROM bank number, `0x00` is treated as `0x01`.
+    pub bank_1: u8,
+    /// `0x4000..=0x5FFF` - 2-bit RAM bank number, or the upper bits of the ROM bank number.
+    pub bank_2: u8,
+    /// `0x6000..=0x7FFF` - If set, `bank_2` also affects `0x0000..=0x3FFF` and external RAM.
+    pub banking_mode: bool,
+    /// MBC1M multicart wiring, where `bank_2` is shifted by 4 bits instead of 5 and bit 4 of `bank_1` is ignored.
+    pub multicart: bool,
+    /// The number of 16KiB ROM banks on the cartridge.
+    pub rom_banks: usize,
+    pub ram: Vec<u8>,
+}
+
+impl MBC1 {
+    pub fn new(rom_banks: usize, ram_size: usize, multicart: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            bank_1: 0x01,
+            bank_2: 0x00,
+            banking_mode: false,
+            multicart,
+            rom_banks,
+            ram: vec![0x00; ram_size],
+        }
+    }
+
+    /// Returns the ROM bank mapped to `address`.
+    fn get_rom_bank(&self, address: u16) -> usize {
+        let (shift, bank_1_mask) = match self.multicart {
+            true => (4, 0b0_1111),
+            false => (5, 0b1_1111),
+        };
+        let bank = match address {
+            0x0000..=0x3FFF if self.banking_mode => self.bank_2 << shift,
+            0x0000..=0x3FFF => 0x00,
+            _ => (self.bank_2 << shift) | (self.bank_1 & bank_1_mask),
+        };
+        // ? Unused upper bits are ignored by carts with less ROM.
+        bank as usize & (self.rom_banks - 1)
+    }
+
+    /// Returns the index into external RAM of `address`.
+    fn get_ram_index(&self, address: u16) -> usize {
+        let bank = match self.banking_mode {
+            true => self.bank_2 as usize,
+            false => 0x00,
+        };
+        (bank * 0x2000 + (address as usize - 0xA000)) & (self.ram.len() - 1)
+    }
+
+    pub fn read_rom(&self, cartridge: &Cartridge, address: u16) -> u8 {
+        let bank = self.get_rom_bank(address);
+        cartridge.read_rom(bank * 0x4000 + (address as usize & 0x3FFF))
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // ? The zero check is done on the full 5 bits, even when the cart has fewer ROM banks.
+                self.bank_1 = match value & 0b1_1111 {
+                    0x00 => 0x01,
+                    bank => bank,
+                };
+            }
+            0x4000..=0x5FFF => self.bank_2 = value & 0b11,
+            0x6000..=0x7FFF => self.banking_mode = value & 0b1 != 0,
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.get_ram_index(address)]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled && !self.ram.is_empty() {
+            let index = self.get_ram_index(address);
+            self.ram[index] = value;
+        }
+    }
+}
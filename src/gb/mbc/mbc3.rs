@@ -0,0 +1,250 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gb::cartridge::Cartridge;
+
+/// The number of t-cycles in one second of emulated time.
+const RTC_CYCLES_PER_SECOND: u32 = 4194304;
+
+/// The clock counter registers, selected by writing `0x08..=0x0C` to `0x4000..=0x5FFF`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RTCRegisters {
+    /// `0x08` - Seconds, `0..=59`.
+    pub seconds: u8,
+    /// `0x09` - Minutes, `0..=59`.
+    pub minutes: u8,
+    /// `0x0A` - Hours, `0..=23`.
+    pub hours: u8,
+    /// `0x0B` - Lower 8 bits of the day counter.
+    pub days_low: u8,
+    /// `0x0C` - Upper bit of the day counter, halt flag and day counter carry.
+    /// * bit 0: Day counter bit 8
+    /// * bit 6: Halt (0 = clock active)
+    /// * bit 7: Day counter carry
+    pub days_high: u8,
+}
+
+impl RTCRegisters {
+    pub fn is_halted(&self) -> bool {
+        self.days_high & 0b0100_0000 != 0
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds | 0b1100_0000,
+            0x09 => self.minutes | 0b1100_0000,
+            0x0A => self.hours | 0b1110_0000,
+            0x0B => self.days_low,
+            0x0C => self.days_high | 0b0011_1110,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0b0011_1111,
+            0x09 => self.minutes = value & 0b0011_1111,
+            0x0A => self.hours = value & 0b0001_1111,
+            0x0B => self.days_low = value,
+            0x0C => self.days_high = value & 0b1100_0001,
+            _ => {}
+        }
+    }
+
+    /// Advance the clock by one second.
+    ///
+    /// Out of range values (set by the game) keep counting until they overflow their bits, without carrying.
+    pub fn tick(&mut self) {
+        if self.seconds != 59 {
+            self.seconds = (self.seconds + 1) & 0b0011_1111;
+            return;
+        }
+        self.seconds = 0;
+        if self.minutes != 59 {
+            self.minutes = (self.minutes + 1) & 0b0011_1111;
+            return;
+        }
+        self.minutes = 0;
+        if self.hours != 23 {
+            self.hours = (self.hours + 1) & 0b0001_1111;
+            return;
+        }
+        self.hours = 0;
+        let (days_low, overflow) = self.days_low.overflowing_add(1);
+        self.days_low = days_low;
+        if overflow {
+            if self.days_high & 0b1 != 0 {
+                // ? The day counter has overflowed past 511.
+                self.days_high = (self.days_high & !0b1) | 0b1000_0000;
+            } else {
+                self.days_high |= 0b1;
+            }
+        }
+    }
+
+    /// Each register as a little-endian `u32`, as used by the RTC save footer.
+    fn to_footer(self) -> [u8; 20] {
+        let mut footer = [0x00; 20];
+        for (i, value) in [self.seconds, self.minutes, self.hours, self.days_low, self.days_high]
+            .into_iter()
+            .enumerate()
+        {
+            footer[i * 4] = value;
+        }
+        footer
+    }
+
+    fn from_footer(footer: &[u8]) -> Self {
+        let mut registers = Self::default();
+        for (i, register) in (0x08..=0x0C).enumerate() {
+            registers.write(register, footer[i * 4]);
+        }
+        registers
+    }
+}
+
+/// [pandocs](https://gbdev.io/pandocs/MBC3.html).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MBC3 {
+    /// `0x0000..=0x1FFF` - External RAM and the RTC registers are only accessible if `0x_A` is written here.
+    pub ram_enabled: bool,
+    /// `0x2000..=0x3FFF` - 7-bit ROM bank number, `0x00` is treated as `0x01`.
+    pub rom_bank: u8,
+    /// `0x4000..=0x5FFF` - RAM bank number (`0x00..=0x07`) or RTC register (`0x08..=0x0C`) mapped to `0xA000..=0xBFFF`.
+    pub ram_bank: u8,
+    /// `0x6000..=0x7FFF` - The last value written, the clock is latched when `0x00` then `0x01` is written.
+    pub latch_clock: u8,
+    /// The clock registers that are updated every second.
+    pub rtc: RTCRegisters,
+    /// The clock registers that are read and written by the game, copied from `rtc` when latched.
+    pub latched_rtc: RTCRegisters,
+    /// The number of t-cycles since the last second tick.
+    pub rtc_cycles: u32,
+    /// Whether this cartridge actually has a timer (`MBC3+TIMER+...` types).
+    pub has_rtc: bool,
+    /// The number of 16KiB ROM banks on the cartridge.
+    pub rom_banks: usize,
+    pub ram: Vec<u8>,
+}
+
+impl MBC3 {
+    pub fn new(rom_banks: usize, ram_size: usize, has_rtc: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            latch_clock: 0xFF,
+            rtc: RTCRegisters::default(),
+            latched_rtc: RTCRegisters::default(),
+            rtc_cycles: 0,
+            has_rtc,
+            rom_banks,
+            ram: vec![0x00; ram_size],
+        }
+    }
+
+    /// Update the RTC as if 4 t-cycles have passed.
+    ///
+    /// The clock runs off of emulated time rather than the host's clock, so runs are deterministic.
+    pub fn update(&mut self) {
+        if !self.has_rtc || self.rtc.is_halted() {
+            return;
+        }
+        self.rtc_cycles += 4;
+        if self.rtc_cycles >= RTC_CYCLES_PER_SECOND {
+            self.rtc_cycles -= RTC_CYCLES_PER_SECOND;
+            self.rtc.tick();
+        }
+    }
+
+    pub fn read_rom(&self, cartridge: &Cartridge, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0x00,
+            _ => self.rom_bank as usize & (self.rom_banks - 1),
+        };
+        cartridge.read_rom(bank * 0x4000 + (address as usize & 0x3FFF))
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0b0111_1111 {
+                    0x00 => 0x01,
+                    bank => bank,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            0x6000..=0x7FFF => {
+                if self.latch_clock == 0x00 && value == 0x01 {
+                    self.latched_rtc = self.rtc;
+                }
+                self.latch_clock = value;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_bank {
+            0x00..=0x07 if !self.ram.is_empty() => self.ram[self.get_ram_index(address)],
+            0x08..=0x0C if self.has_rtc => self.latched_rtc.read(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match self.ram_bank {
+            0x00..=0x07 if !self.ram.is_empty() => {
+                let index = self.get_ram_index(address);
+                self.ram[index] = value;
+            }
+            0x08..=0x0C if self.has_rtc => {
+                // ? Writes go to both the live and latched registers.
+                self.rtc.write(self.ram_bank, value);
+                self.latched_rtc.write(self.ram_bank, value);
+                if self.ram_bank == 0x08 {
+                    // ? Writing to the seconds register resets the sub-second counter.
+                    self.rtc_cycles = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the index into external RAM of `address`.
+    fn get_ram_index(&self, address: u16) -> usize {
+        (self.ram_bank as usize * 0x2000 + (address as usize - 0xA000)) & (self.ram.len() - 1)
+    }
+
+    /// Returns the 48-byte RTC footer appended to battery saves by most emulators (BGB/VBA-M format):
+    /// the live registers then the latched registers as little-endian `u32`s, followed by a 64-bit UNIX timestamp.
+    pub fn get_rtc_footer(&self) -> [u8; 48] {
+        let mut footer = [0x00; 48];
+        footer[0..20].copy_from_slice(&self.rtc.to_footer());
+        footer[20..40].copy_from_slice(&self.latched_rtc.to_footer());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        footer[40..48].copy_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    /// Loads the registers from an RTC footer, accepting both the 48-byte and the older 44-byte (32-bit timestamp) variants.
+    ///
+    /// The timestamp is ignored rather than catching up with the host's clock, to keep runs deterministic.
+    pub fn load_rtc_footer(&mut self, footer: &[u8]) {
+        if footer.len() >= 40 {
+            self.rtc = RTCRegisters::from_footer(&footer[0..20]);
+            self.latched_rtc = RTCRegisters::from_footer(&footer[20..40]);
+            self.rtc_cycles = 0;
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::gb::cartridge::Cartridge;
+
+/// [pandocs](https://gbdev.io/pandocs/MBC5.html).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MBC5 {
+    /// `0x0000..=0x1FFF` - External RAM is only accessible if `0x_A` is written here.
+    pub ram_enabled: bool,
+    /// `0x2000..=0x2FFF` (lower 8 bits) and `0x3000..=0x3FFF` (bit 8) - 9-bit ROM bank number, `0x000` is a valid bank.
+    pub rom_bank: u16,
+    /// `0x4000..=0x5FFF` - 4-bit RAM bank number (3-bit on rumble carts).
+    pub ram_bank: u8,
+    /// Whether the cart has a rumble motor, wired to bit 3 of the RAM bank register.
+    pub has_rumble: bool,
+    /// `true` while the rumble motor is on.
+    pub rumble: bool,
+    /// The number of 16KiB ROM banks on the cartridge.
+    pub rom_banks: usize,
+    pub ram: Vec<u8>,
+}
+
+impl MBC5 {
+    pub fn new(rom_banks: usize, ram_size: usize, has_rumble: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 0x001,
+            ram_bank: 0x00,
+            has_rumble,
+            rumble: false,
+            rom_banks,
+            ram: vec![0x00; ram_size],
+        }
+    }
+
+    pub fn read_rom(&self, cartridge: &Cartridge, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0x000,
+            _ => self.rom_bank as usize & (self.rom_banks - 1),
+        };
+        cartridge.read_rom(bank * 0x4000 + (address as usize & 0x3FFF))
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0x0FF) | ((value as u16 & 0b1) << 8),
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & 0b1000 != 0;
+                    self.ram_bank = value & 0b0111;
+                } else {
+                    self.ram_bank = value & 0b1111;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        self.ram[self.get_ram_index(address)]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled && !self.ram.is_empty() {
+            let index = self.get_ram_index(address);
+            self.ram[index] = value;
+        }
+    }
+
+    /// Returns the index into external RAM of `address`.
+    fn get_ram_index(&self, address: u16) -> usize {
+        (self.ram_bank as usize * 0x2000 + (address as usize - 0xA000)) & (self.ram.len() - 1)
+    }
+}
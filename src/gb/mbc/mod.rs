@@ -0,0 +1,3 @@
+pub mod mbc1;
+pub mod mbc3;
+pub mod mbc5;
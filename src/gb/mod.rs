@@ -1,9 +1,16 @@
 pub mod emu;
 pub mod utils;
 
+pub mod boot;
 pub mod bus;
 pub mod cartridge;
 pub mod cpu;
+pub mod mbc;
+pub mod save_state;
+pub mod sgb;
+
+pub mod headless;
+pub mod wav;
 
 pub mod instructions;
 pub mod io;
@@ -0,0 +1,161 @@
+use std::io::{Error, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bus::{HRAM, WRAM},
+    cartridge::MBC,
+    cpu::CPU,
+    emu::GameboyEmulator,
+    instructions::instructions::Instruction,
+    io::{
+        graphics::{OAM, PPU, VRAM},
+        io_registers::IORegisters,
+        joypad::JoypadState,
+    },
+    sgb::SGB,
+    utils::{CPUState, IME},
+};
+
+/// Identifies a Loki save state.
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"LOKI";
+/// Incremented whenever the layout of [`SaveState`] changes, older states are rejected.
+pub const SAVE_STATE_VERSION: u32 = 1;
+
+/// A snapshot of the entire machine, taken between instructions.
+///
+/// Instructions are stored as chains of closures (see [`Instruction`]) which cannot be serialized,
+/// so states are only taken once the current instruction has completed and restored with no instruction in flight.
+///
+/// The binary format is [`SAVE_STATE_MAGIC`], [`SAVE_STATE_VERSION`] as a little-endian `u32`,
+/// then the [`bincode`]-encoded `SaveState`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveState {
+    /// The cartridge's `header_checksum` and `global_checksum`, to catch states being loaded into the wrong game.
+    pub rom_checksum: [u8; 3],
+    pub cpu: CPU,
+    pub ime: IME,
+    pub cpu_state: CPUState,
+    pub halt_bug: bool,
+    pub ppu: PPU,
+    pub vram: VRAM,
+    pub wram: WRAM,
+    pub oam: OAM,
+    pub hram: HRAM,
+    pub mbc: MBC,
+    pub io_registers: IORegisters,
+    pub sgb: Option<SGB>,
+}
+
+/// Borrowed version of [`SaveState`], serializes to the same layout without cloning the emulator.
+#[derive(Serialize)]
+struct SaveStateRef<'a> {
+    rom_checksum: [u8; 3],
+    cpu: &'a CPU,
+    ime: &'a IME,
+    cpu_state: CPUState,
+    halt_bug: bool,
+    ppu: &'a PPU,
+    vram: &'a VRAM,
+    wram: &'a WRAM,
+    oam: &'a OAM,
+    hram: &'a HRAM,
+    mbc: &'a MBC,
+    io_registers: &'a IORegisters,
+    sgb: &'a Option<SGB>,
+}
+
+fn get_rom_checksum(emu: &GameboyEmulator) -> [u8; 3] {
+    let cartridge = &emu.bus.cartridge;
+    [
+        cartridge.header_checksum()[0],
+        cartridge.global_checksum()[0],
+        cartridge.global_checksum()[1],
+    ]
+}
+
+impl GameboyEmulator {
+    /// Keeps updating the emulator until the current instruction has completed, so that a state can be saved.
+    pub fn finish_instruction(&mut self, joypad: JoypadState) {
+        while !self.current_instruction.has_completed() {
+            self.update(joypad);
+        }
+    }
+
+    /// Serializes the entire machine, failing if an instruction is still in progress (see [`SaveState`]).
+    pub fn save_state(&self) -> std::io::Result<Vec<u8>> {
+        if !self.current_instruction.has_completed() {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "GB - Save states can only be taken between instructions!",
+            ));
+        }
+        let state = SaveStateRef {
+            rom_checksum: get_rom_checksum(self),
+            cpu: &self.cpu,
+            ime: &self.ime,
+            cpu_state: self.cpu_state,
+            halt_bug: self.halt_bug,
+            ppu: &self.ppu,
+            vram: &self.bus.vram,
+            wram: &self.bus.wram,
+            oam: &self.bus.oam,
+            hram: &self.bus.hram,
+            mbc: &self.bus.mbc,
+            io_registers: &self.io_registers,
+            sgb: &self.sgb,
+        };
+
+        let mut data = SAVE_STATE_MAGIC.to_vec();
+        data.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, &state)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        Ok(data)
+    }
+
+    /// Restores the entire machine from a state created by [`GameboyEmulator::save_state`].
+    pub fn load_state(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if data.len() < 8 || data[0..4] != SAVE_STATE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "GB - Not a save state!"));
+        }
+        let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        if version != SAVE_STATE_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("GB - Save state version {version} is not supported (expected {SAVE_STATE_VERSION})!"),
+            ));
+        }
+        let state: SaveState = bincode::deserialize(&data[8..])
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        if state.rom_checksum != get_rom_checksum(self) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "GB - Save state was created with a different ROM!",
+            ));
+        }
+
+        self.cpu = state.cpu;
+        self.ime = state.ime;
+        self.cpu_state = state.cpu_state;
+        self.halt_bug = state.halt_bug;
+        // ? The renderer, palette and access restrictions are frontend options rather than machine state.
+        let (renderer, palette, access_restrictions) = (
+            self.ppu.renderer,
+            self.ppu.palette,
+            self.ppu.access_restrictions,
+        );
+        self.ppu = state.ppu;
+        self.ppu.renderer = renderer;
+        self.ppu.palette = palette;
+        self.ppu.access_restrictions = access_restrictions;
+        self.bus.vram = state.vram;
+        self.bus.wram = state.wram;
+        self.bus.oam = state.oam;
+        self.bus.hram = state.hram;
+        self.bus.mbc = state.mbc;
+        self.io_registers = state.io_registers;
+        self.sgb = state.sgb;
+        self.current_instruction = Instruction::default();
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cartridge::Cartridge,
+    emu::GameboyEmulator,
+    io::graphics::{rgb555_to_rgb888, LCDC_BG_MAP, SCREEN_HEIGHT, SCREEN_WIDTH},
+    utils::get_bit,
+};
+
+/// Width of the SGB's output, including the border.
+pub const SGB_SCREEN_WIDTH: usize = 256;
+/// Height of the SGB's output, including the border.
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+/// Where the Game Boy's screen is drawn within the border.
+pub const SGB_SCREEN_X: usize = 48;
+/// Where the Game Boy's screen is drawn within the border.
+pub const SGB_SCREEN_Y: usize = 40;
+
+/// The size of the data sent by `*_TRN` commands, 256 tiles taken from the screen.
+pub const SGB_TRANSFER_SIZE: usize = 0x1000;
+/// The number of 8x8 attribute cells on the screen.
+const ATTRIBUTE_MAP_SIZE: usize = (SCREEN_WIDTH / 8) * (SCREEN_HEIGHT / 8);
+/// The size of an attribute file sent by `ATTR_TRN`, 2 bits per cell.
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_MAP_SIZE / 4;
+const ATTRIBUTE_FILES: usize = 45;
+/// The size of the border's tile map (32x32 entries) followed by its 4 palettes of 16 colors.
+const BORDER_MAP_SIZE: usize = 0x880;
+
+/// The palette used until a game sets its own.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// [pandocs](https://gbdev.io/pandocs/SGB_Command_Summary.html), command IDs sent in the first byte of a packet.
+pub mod command {
+    pub const PAL01: u8 = 0x00;
+    pub const PAL23: u8 = 0x01;
+    pub const PAL03: u8 = 0x02;
+    pub const PAL12: u8 = 0x03;
+    pub const ATTR_BLK: u8 = 0x04;
+    pub const ATTR_LIN: u8 = 0x05;
+    pub const ATTR_DIV: u8 = 0x06;
+    pub const ATTR_CHR: u8 = 0x07;
+    pub const PAL_SET: u8 = 0x0A;
+    pub const PAL_TRN: u8 = 0x0B;
+    pub const MLT_REQ: u8 = 0x11;
+    pub const CHR_TRN: u8 = 0x13;
+    pub const PCT_TRN: u8 = 0x14;
+    pub const ATTR_TRN: u8 = 0x15;
+    pub const ATTR_SET: u8 = 0x16;
+    pub const MASK_EN: u8 = 0x17;
+}
+
+/// [pandocs](https://gbdev.io/pandocs/SGB_Functions.html), the Super Game Boy's command packets, colors and border.
+///
+/// Commands are run as soon as their last packet arrives, and VRAM transfers read the 256 tiles shown on the BG map
+/// at that moment rather than waiting for the next frame.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SGB {
+    /// Packets are ignored unless the cartridge header says the game supports the SGB.
+    pub commands_enabled: bool,
+    /// The `P14`/`P15` lines from the last write to `P1`.
+    lines: u8,
+    /// Set by a reset pulse, until the stop bit of the packet.
+    receiving: bool,
+    /// Bits are only read after both lines have been released since the last pulse.
+    pulse_ready: bool,
+    /// The number of bits of `packet` received so far, `128` once waiting for the stop bit.
+    bit_index: u8,
+    packet: [u8; 16],
+    /// Every packet received so far of a multi-packet command.
+    command_data: Vec<u8>,
+    packets_left: u8,
+    /// 4 palettes of 4 RGB555 colors, color 0 is shared by all of them.
+    pub palettes: [[u16; 4]; 4],
+    /// 512 palettes of 4 colors, sent by `PAL_TRN` and selected by `PAL_SET`.
+    pub system_palettes: Vec<u16>,
+    /// The palette of each 8x8 cell of the screen, 20x18.
+    pub attribute_map: Vec<u8>,
+    /// Sent by `ATTR_TRN` and applied by `ATTR_SET` or `PAL_SET`.
+    pub attribute_files: Vec<u8>,
+    /// 256 4bpp SNES tiles, sent by `CHR_TRN`.
+    pub border_tiles: Vec<u8>,
+    /// The border's tile map and palettes, sent by `PCT_TRN`.
+    pub border_map: Vec<u8>,
+    /// Set by `MASK_EN`.
+    /// * `0`: Show the screen
+    /// * `1`: Freeze the screen
+    /// * `2`: Black screen
+    /// * `3`: Screen filled with color 0
+    pub mask: u8,
+    /// The number of joypads enabled by `MLT_REQ`, `1`, `2` or `4`.
+    pub players: u8,
+    pub player: u8,
+    /// The `SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT` output with the border, updated at the start of each VBlank.
+    pub frame_buffer: Vec<u32>,
+}
+
+impl SGB {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        Self {
+            // ? The SGB BIOS checks for both the SGB flag and the new licensee code.
+            commands_enabled: cartridge.sgb_flag()[0] == 0x03
+                && cartridge.old_licensee_code()[0] == 0x33,
+            lines: 0x30,
+            receiving: false,
+            pulse_ready: false,
+            bit_index: 0,
+            packet: [0x00; 16],
+            command_data: Vec::new(),
+            packets_left: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![0x0000; 512 * 4],
+            attribute_map: vec![0; ATTRIBUTE_MAP_SIZE],
+            attribute_files: vec![0x00; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            border_tiles: vec![0x00; SGB_TRANSFER_SIZE * 2],
+            border_map: vec![0x00; BORDER_MAP_SIZE],
+            mask: 0,
+            players: 1,
+            player: 0,
+            frame_buffer: vec![0x00000000; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT],
+        }
+    }
+
+    /// Returns the color of `shade` at screen position (`x`, `y`).
+    #[inline]
+    pub fn get_color(&self, x: usize, y: usize, shade: u8) -> u32 {
+        let palette = self.attribute_map[(y / 8) * (SCREEN_WIDTH / 8) + x / 8];
+        rgb555_to_rgb888(self.palettes[palette as usize][shade as usize])
+    }
+
+    /// Returns `P1` with `players` joypads connected, of which only the first has any buttons pressed.
+    pub fn get_joypad_state(&self, input_state: u8) -> u8 {
+        if self.players == 1 {
+            return input_state;
+        }
+        match input_state & 0x30 {
+            // ? With neither line selected, the lower nibble reads the current joypad's ID.
+            0x30 => (input_state & 0xF0) | (0xF - self.player),
+            _ if self.player != 0 => input_state | 0x0F,
+            _ => input_state,
+        }
+    }
+
+    /// [pandocs](https://gbdev.io/pandocs/SGB_Command_Packet.html), decodes packets from writes to `P1`.
+    ///
+    /// A reset pulse (both lines low) starts a packet, then each pulse of `P14` is a `0` and each pulse of `P15` a `1`,
+    /// 128 bits sent least significant first, followed by a `0` stop bit.
+    pub fn write_joypad(emu: &mut GameboyEmulator, value: u8) {
+        let Some(sgb) = emu.sgb.as_mut() else {
+            return;
+        };
+        let lines = value & 0x30;
+        let prev_lines = std::mem::replace(&mut sgb.lines, lines);
+        match lines {
+            0x00 => {
+                sgb.receiving = true;
+                sgb.pulse_ready = false;
+                sgb.bit_index = 0;
+                sgb.packet = [0x00; 16];
+            }
+            0x30 => {
+                // ? The next joypad is selected once `P15` is released, as games do after reading the buttons.
+                if !sgb.receiving && !get_bit(prev_lines, 0b0010_0000) && sgb.players > 1 {
+                    sgb.player = (sgb.player + 1) % sgb.players;
+                }
+                sgb.pulse_ready = true;
+            }
+            _ if sgb.receiving && sgb.pulse_ready => {
+                sgb.pulse_ready = false;
+                let bit = lines == 0x10;
+                if sgb.bit_index < 128 {
+                    if bit {
+                        sgb.packet[sgb.bit_index as usize / 8] |= 1 << (sgb.bit_index % 8);
+                    }
+                    sgb.bit_index += 1;
+                    return;
+                }
+
+                sgb.receiving = false;
+                if !bit && sgb.commands_enabled {
+                    Self::receive_packet(emu);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Collects the packets of a command, running it once the last one arrives.
+    fn receive_packet(emu: &mut GameboyEmulator) {
+        let Some(sgb) = emu.sgb.as_mut() else {
+            return;
+        };
+        if sgb.packets_left == 0 {
+            sgb.command_data.clear();
+            sgb.packets_left = (sgb.packet[0] & 0b111).max(1);
+        }
+        let packet = sgb.packet;
+        sgb.command_data.extend_from_slice(&packet);
+        sgb.packets_left -= 1;
+        if sgb.packets_left == 0 {
+            let data = std::mem::take(&mut sgb.command_data);
+            Self::run_command(emu, &data);
+        }
+    }
+
+    fn run_command(emu: &mut GameboyEmulator, data: &[u8]) {
+        let transfer = match data[0] >> 3 {
+            command::PAL_TRN | command::CHR_TRN | command::PCT_TRN | command::ATTR_TRN => {
+                Self::get_vram_transfer(emu)
+            }
+            _ => Vec::new(),
+        };
+        let Some(sgb) = emu.sgb.as_mut() else {
+            return;
+        };
+        let get_color = |i: usize| u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]);
+        match data[0] >> 3 {
+            command::PAL01 => sgb.set_palettes(0, 1, get_color),
+            command::PAL23 => sgb.set_palettes(2, 3, get_color),
+            command::PAL03 => sgb.set_palettes(0, 3, get_color),
+            command::PAL12 => sgb.set_palettes(1, 2, get_color),
+            command::ATTR_BLK => sgb.attribute_block(data),
+            command::ATTR_LIN => {
+                for &line in data[2..].iter().take(data[1] as usize) {
+                    let (index, palette) = ((line & 0x1F) as usize, (line >> 5) & 0b11);
+                    match get_bit(line, 0b1000_0000) {
+                        true if index < SCREEN_HEIGHT / 8 => {
+                            sgb.attribute_map[index * 20..(index + 1) * 20].fill(palette)
+                        }
+                        false if index < SCREEN_WIDTH / 8 => {
+                            for y in 0..SCREEN_HEIGHT / 8 {
+                                sgb.attribute_map[y * 20 + index] = palette;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            command::ATTR_DIV => {
+                let (control, coordinate) = (data[1], data[2] as usize);
+                let [below, above, on] = [0, 2, 4].map(|shift| (control >> shift) & 0b11);
+                for y in 0..SCREEN_HEIGHT / 8 {
+                    for x in 0..SCREEN_WIDTH / 8 {
+                        let position = match get_bit(control, 0b0100_0000) {
+                            true => y,
+                            false => x,
+                        };
+                        sgb.attribute_map[y * 20 + x] = match position.cmp(&coordinate) {
+                            std::cmp::Ordering::Less => above,
+                            std::cmp::Ordering::Equal => on,
+                            std::cmp::Ordering::Greater => below,
+                        };
+                    }
+                }
+            }
+            command::ATTR_CHR => {
+                let (mut x, mut y) = ((data[1] as usize).min(19), (data[2] as usize).min(17));
+                let count = u16::from_le_bytes([data[3], data[4]]).min(360) as usize;
+                let vertical = get_bit(data[5], 0b1);
+                for i in 0..count.min((data.len() - 6) * 4) {
+                    sgb.attribute_map[y * 20 + x] = get_packed_palette(&data[6..], i);
+                    if vertical {
+                        y += 1;
+                        if y == SCREEN_HEIGHT / 8 {
+                            (x, y) = ((x + 1) % 20, 0);
+                        }
+                    } else {
+                        x += 1;
+                        if x == SCREEN_WIDTH / 8 {
+                            (x, y) = (0, (y + 1) % 18);
+                        }
+                    }
+                }
+            }
+            command::PAL_SET => {
+                for (i, palette) in sgb.palettes.iter_mut().enumerate() {
+                    let index = (get_color(i) & 0x01FF) as usize * 4;
+                    palette.copy_from_slice(&sgb.system_palettes[index..index + 4]);
+                }
+                let shared = sgb.palettes[0][0];
+                sgb.palettes
+                    .iter_mut()
+                    .for_each(|palette| palette[0] = shared);
+                sgb.set_attribute_file(data[9]);
+            }
+            command::PAL_TRN => {
+                for (color, bytes) in sgb.system_palettes.iter_mut().zip(transfer.chunks_exact(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            command::MLT_REQ => {
+                sgb.players = match data[1] & 0b11 {
+                    0b01 => 2,
+                    0b11 => 4,
+                    _ => 1,
+                };
+                sgb.player = 0;
+            }
+            command::CHR_TRN => {
+                let offset = (data[1] & 0b1) as usize * SGB_TRANSFER_SIZE;
+                sgb.border_tiles[offset..offset + SGB_TRANSFER_SIZE].copy_from_slice(&transfer);
+            }
+            command::PCT_TRN => sgb.border_map.copy_from_slice(&transfer[..BORDER_MAP_SIZE]),
+            command::ATTR_TRN => {
+                let size = sgb.attribute_files.len();
+                sgb.attribute_files.copy_from_slice(&transfer[..size]);
+            }
+            command::ATTR_SET => sgb.set_attribute_file(data[1] | 0b1000_0000),
+            command::MASK_EN => sgb.mask = data[1] & 0b11,
+            // ? Sound, SNES and system commands aren't supported.
+            _ => {}
+        }
+    }
+
+    /// Sets colors 1-3 of palettes `a` and `b`, and the shared color 0, from the 7 colors of a `PALxx` command.
+    fn set_palettes(&mut self, a: usize, b: usize, get_color: impl Fn(usize) -> u16) {
+        let shared = get_color(0);
+        self.palettes
+            .iter_mut()
+            .for_each(|palette| palette[0] = shared);
+        for i in 1..4 {
+            self.palettes[a][i] = get_color(i);
+            self.palettes[b][i] = get_color(i + 3);
+        }
+    }
+
+    /// `ATTR_BLK`, colors the inside, surrounding line and outside of each block.
+    fn attribute_block(&mut self, data: &[u8]) {
+        for block in data[2..].chunks_exact(6).take(data[1] as usize) {
+            let [control, palettes, x1, y1, x2, y2] = [0, 1, 2, 3, 4, 5].map(|i| block[i] as usize);
+            let [inside, mut line, outside] =
+                [0, 2, 4].map(|shift| (palettes >> shift) as u8 & 0b11);
+            let mut change_line = get_bit(control as u8, 0b010);
+            // ? Changing only the inside or only the outside gives the surrounding line the same color.
+            match control & 0b111 {
+                0b001 => (line, change_line) = (inside, true),
+                0b100 => (line, change_line) = (outside, true),
+                _ => {}
+            }
+            for y in 0..SCREEN_HEIGHT / 8 {
+                for x in 0..SCREEN_WIDTH / 8 {
+                    let in_block = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_line = in_block && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let palette = match (in_block, on_line) {
+                        (_, true) if change_line => line,
+                        (true, false) if get_bit(control as u8, 0b001) => inside,
+                        (false, _) if get_bit(control as u8, 0b100) => outside,
+                        _ => continue,
+                    };
+                    self.attribute_map[y * 20 + x] = palette;
+                }
+            }
+        }
+    }
+
+    /// Applies attribute file `control & 0x3F` if bit 7 is set, and cancels `MASK_EN` if bit 6 is set.
+    fn set_attribute_file(&mut self, control: u8) {
+        let index = (control & 0b0011_1111) as usize;
+        if get_bit(control, 0b1000_0000) && index < ATTRIBUTE_FILES {
+            let file = &self.attribute_files[index * ATTRIBUTE_FILE_SIZE..][..ATTRIBUTE_FILE_SIZE];
+            for (i, palette) in self.attribute_map.iter_mut().enumerate() {
+                *palette = get_packed_palette(file, i);
+            }
+        }
+        if get_bit(control, 0b0100_0000) {
+            self.mask = 0;
+        }
+    }
+
+    /// Returns the 256 tiles (4KiB) shown on the screen, left to right and top to bottom, as used by `*_TRN` commands.
+    fn get_vram_transfer(emu: &GameboyEmulator) -> Vec<u8> {
+        let graphics = &emu.io_registers.graphics;
+        let vram = &emu.bus.vram;
+        let map = match get_bit(graphics.LCDC, LCDC_BG_MAP) {
+            true => 0x1C00,
+            false => 0x1800,
+        };
+        (0..SGB_TRANSFER_SIZE / 16)
+            .flat_map(|tile| {
+                let tile_id = vram[map + (tile / 20) * 32 + tile % 20];
+                let address = (graphics.get_bg_tile_address(tile_id) - 0x8000) as usize;
+                (0..16).map(move |i| vram[address + i])
+            })
+            .collect()
+    }
+
+    /// Draws the border and the screen (unless masked) into [`SGB::frame_buffer`].
+    pub fn compose_frame(emu: &mut GameboyEmulator) {
+        let Some(sgb) = emu.sgb.as_mut() else {
+            return;
+        };
+        let backdrop = rgb555_to_rgb888(sgb.palettes[0][0]);
+        for y in 0..SGB_SCREEN_HEIGHT {
+            for x in 0..SGB_SCREEN_WIDTH {
+                let in_screen = (SGB_SCREEN_X..SGB_SCREEN_X + SCREEN_WIDTH).contains(&x)
+                    && (SGB_SCREEN_Y..SGB_SCREEN_Y + SCREEN_HEIGHT).contains(&y);
+                let color = match (in_screen, sgb.mask) {
+                    (false, _) => sgb.get_border_color(x, y).unwrap_or(backdrop),
+                    (true, 0) => {
+                        emu.ppu.frame_buffer[(y - SGB_SCREEN_Y) * SCREEN_WIDTH + x - SGB_SCREEN_X]
+                    }
+                    (true, 1) => continue,
+                    (true, 2) => 0x00000000,
+                    (true, _) => backdrop,
+                };
+                sgb.frame_buffer[y * SGB_SCREEN_WIDTH + x] = color;
+            }
+        }
+    }
+
+    /// Returns the border's color at (`x`, `y`), or `None` if it is transparent.
+    fn get_border_color(&self, x: usize, y: usize) -> Option<u32> {
+        // ? Each map entry is the tile ID, the palette (4-7) in bits 10-12, X flip in bit 14 and Y flip in bit 15.
+        let index = ((y / 8) * 32 + x / 8) * 2;
+        let entry = u16::from_le_bytes([self.border_map[index], self.border_map[index + 1]]);
+        let column = match get_bit(entry, 0x4000u16) {
+            true => 7 - x % 8,
+            false => x % 8,
+        };
+        let row = match get_bit(entry, 0x8000u16) {
+            true => 7 - y % 8,
+            false => y % 8,
+        };
+
+        // ? SNES 4bpp tiles store bitplanes 0 and 1 interleaved, followed by bitplanes 2 and 3.
+        let tile = &self.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+        let color = [
+            tile[row * 2],
+            tile[row * 2 + 1],
+            tile[16 + row * 2],
+            tile[17 + row * 2],
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |color, (plane, bits)| {
+            color | ((bits >> (7 - column)) & 0b1) << plane
+        }) as usize;
+        if color == 0 {
+            return None;
+        }
+        let palette = ((entry >> 10) & 0b11) as usize;
+        let index = 0x800 + palette * 32 + color * 2;
+        Some(rgb555_to_rgb888(u16::from_le_bytes([
+            self.border_map[index],
+            self.border_map[index + 1],
+        ])))
+    }
+}
+
+/// Returns the `i`th 2-bit palette number packed into `data`, 4 per byte from the most significant bits.
+#[inline]
+fn get_packed_palette(data: &[u8], i: usize) -> u8 {
+    (data[i / 4] >> (6 - (i % 4) * 2)) & 0b11
+}
@@ -1,20 +1,9 @@
 #[test]
 #[cfg(test)]
 fn jsmoo_instruction_tests() -> Result<(), Box<dyn std::error::Error>> {
-    use crate::gb::{
-        bus::{Bus, HRAM, WRAM},
-        cartridge::Cartridge,
-        cpu::CPU,
-        emu::GameboyEmulator,
-        instructions::instructions::Instruction,
-        io::{
-            graphics::{OAM, PPU, VRAM},
-            io_registers::IORegisters,
-        },
-        utils::*,
-    };
+    use crate::gb::{bus::Bus, cartridge::Cartridge, cpu::CPU, emu::GameboyEmulator, utils::*};
     use serde::Deserialize;
-    use std::{fs, time::Instant};
+    use std::fs;
 
     #[derive(Debug, Deserialize)]
     struct JsmooTest {
@@ -40,24 +29,11 @@ fn jsmoo_instruction_tests() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     impl From<&JsmooTestState> for GameboyEmulator {
+        #[allow(clippy::needless_return)]
         fn from(value: &JsmooTestState) -> Self {
-            let mut emu = Self {
-                prev_update: Instant::now(),
-                cpu: CPU::from(value),
-                ppu: PPU::new_init(),
-                ime: IME::Disabled,
-                bus: Bus {
-                    cartridge: Cartridge::new_empty(),
-                    vram: VRAM::new_empty(),
-                    wram: WRAM::new_empty(),
-                    oam: OAM::new_empty(),
-                    hram: HRAM::new_empty(),
-                },
-                is_halted: false,
-                io_registers: IORegisters::new(),
-                current_instruction: Instruction::default(),
-            };
-            Bus::reset_test_ram();
+            let mut emu = Self::new(Cartridge::new_empty());
+            emu.cpu = CPU::from(value);
+            emu.bus.test_ram = Some(Box::new([0x00; 65536]));
             for (address, value) in &value.ram {
                 Bus::write(&mut emu, *address, *value);
             }
@@ -92,7 +68,7 @@ fn jsmoo_instruction_tests() -> Result<(), Box<dyn std::error::Error>> {
     let mut tests_dir = fs::read_dir("./roms/gb/tests/jsmoo/tests")?
         .filter_map(|p| p.ok())
         .collect::<Vec<_>>();
-    tests_dir.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    tests_dir.sort_by_key(|a| a.file_name());
 
     for file_path in tests_dir {
         let file = fs::OpenOptions::new().read(true).open(file_path.path())?;
@@ -102,7 +78,7 @@ fn jsmoo_instruction_tests() -> Result<(), Box<dyn std::error::Error>> {
         for test in tests {
             let mut emu = GameboyEmulator::from(&test.initial);
             for _ in 0..test.cycles.len() {
-                if emu.is_halted {
+                if emu.cpu_state == CPUState::Halted {
                     continue;
                 }
 
@@ -142,3 +118,1431 @@ fn jsmoo_instruction_tests() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(test)]
+fn mbc1_rom_banking() {
+    use crate::gb::cartridge::{Cartridge, MBC};
+
+    // ? 512KiB MBC1 cart where the first byte of each bank is its bank number.
+    let mut rom = vec![0x00; 0x80000];
+    for bank in 0..32 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    rom[0x0147] = 0x01;
+    rom[0x0148] = 0x04;
+    let cartridge = Cartridge::from_rom(rom).unwrap();
+    let mut mbc = cartridge.get_mbc();
+
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x01);
+    mbc.write_rom(0x2000, 0x00);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x01);
+    mbc.write_rom(0x2000, 0x15);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x15);
+    // ? Bank numbers are masked to the cart's 32 banks.
+    mbc.write_rom(0x4000, 0x01);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x15);
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(mbc.read_rom(&cartridge, 0x0000), 0x00);
+
+    // ? ROM size is checked against the header.
+    assert!(Cartridge::from_rom(vec![0x00; 0x4000]).is_err());
+    // ? As are unsupported cartridge types and RAM sizes, rather than panicking later.
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0147] = 0x20;
+    assert!(Cartridge::from_rom(rom.clone()).is_err());
+    rom[0x0147] = 0x00;
+    rom[0x0149] = 0x06;
+    assert!(Cartridge::from_rom(rom.clone()).is_err());
+    rom[0x0149] = 0x00;
+    assert!(Cartridge::from_rom(rom).is_ok());
+    assert!(matches!(Cartridge::new_empty().get_mbc(), MBC::None { .. }));
+}
+
+#[test]
+#[cfg(test)]
+fn mbc3_rtc() {
+    use crate::gb::mbc::mbc3::{RTCRegisters, MBC3};
+
+    let mut mbc = MBC3::new(2, 0x2000, true);
+    mbc.write_rom(0x0000, 0x0A);
+    // ? 23:59:59 on day 511.
+    mbc.write_rom(0x4000, 0x08);
+    mbc.write_ram(0xA000, 59);
+    mbc.write_rom(0x4000, 0x09);
+    mbc.write_ram(0xA000, 59);
+    mbc.write_rom(0x4000, 0x0A);
+    mbc.write_ram(0xA000, 23);
+    mbc.write_rom(0x4000, 0x0B);
+    mbc.write_ram(0xA000, 0xFF);
+    mbc.write_rom(0x4000, 0x0C);
+    mbc.write_ram(0xA000, 0x01);
+
+    for _ in 0..(4194304 / 4) {
+        mbc.update();
+    }
+
+    // ? Registers only change once latched.
+    assert_eq!(mbc.read_ram(0xA000) & 0b1100_0001, 0x01);
+    mbc.write_rom(0x6000, 0x00);
+    mbc.write_rom(0x6000, 0x01);
+    assert_eq!(mbc.read_ram(0xA000) & 0b1100_0001, 0b1000_0000);
+    assert_eq!(
+        mbc.latched_rtc,
+        RTCRegisters {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days_low: 0,
+            days_high: 0b1000_0000,
+        }
+    );
+
+    // ? The clock survives a round trip through the save footer.
+    let footer = mbc.get_rtc_footer();
+    let mut loaded = MBC3::new(2, 0x2000, true);
+    loaded.load_rtc_footer(&footer);
+    assert_eq!(loaded.rtc, mbc.rtc);
+    assert_eq!(loaded.latched_rtc, mbc.latched_rtc);
+}
+
+#[test]
+#[cfg(test)]
+fn mbc5_banking_and_rumble() {
+    use crate::gb::{
+        bus::Bus,
+        cartridge::{Cartridge, MBC},
+        emu::{EmulatorEvent, GameboyEmulator},
+    };
+
+    // ? 8MiB MBC5+RUMBLE+RAM cart where the first two bytes of each bank are its bank number.
+    let mut rom = vec![0x00; 0x800000];
+    for bank in 0..512 {
+        rom[bank * 0x4000] = bank as u8;
+        rom[bank * 0x4000 + 1] = (bank >> 8) as u8;
+    }
+    rom[0x0147] = 0x1D;
+    rom[0x0148] = 0x08;
+    rom[0x0149] = 0x03;
+    let cartridge = Cartridge::from_rom(rom).unwrap();
+    let mut mbc = cartridge.get_mbc();
+    assert!(matches!(mbc, MBC::MBC5(_)));
+
+    mbc.write_rom(0x2000, 0x23);
+    mbc.write_rom(0x3000, 0x01);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x23);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4001), 0x01);
+    // ? Unlike MBC1, bank 0 can be mapped to `0x4000..=0x7FFF`.
+    mbc.write_rom(0x2000, 0x00);
+    mbc.write_rom(0x3000, 0x00);
+    assert_eq!(mbc.read_rom(&cartridge, 0x4000), 0x00);
+
+    // ? Bit 3 of the RAM bank register drives the motor.
+    assert!(!mbc.is_rumbling());
+    mbc.write_rom(0x4000, 0b1010);
+    assert!(mbc.is_rumbling());
+    mbc.write_rom(0x4000, 0b0010);
+    assert!(!mbc.is_rumbling());
+
+    // ? The frontend is told when the motor changes, only the latest state is kept until it polls.
+    let mut emu = GameboyEmulator::new(cartridge);
+    Bus::write(&mut emu, 0x4000, 0b1010);
+    Bus::write(&mut emu, 0x4000, 0b1011);
+    assert_eq!(emu.poll_event(), Some(EmulatorEvent::Rumble(true)));
+    assert_eq!(emu.poll_event(), None);
+    for _ in 0..100 {
+        Bus::write(&mut emu, 0x4000, 0b1010);
+        Bus::write(&mut emu, 0x4000, 0b0010);
+    }
+    assert_eq!(emu.events.len(), 1);
+    assert_eq!(emu.poll_event(), Some(EmulatorEvent::Rumble(false)));
+}
+
+#[test]
+#[cfg(test)]
+fn battery_save_round_trip() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::{EmulatorEvent, GameboyEmulator},
+        io::joypad::JoypadState,
+    };
+
+    let save_path = std::env::temp_dir().join("loki_emu_battery_save_round_trip.sav");
+    let _ = std::fs::remove_file(&save_path);
+
+    // ? MBC1+RAM+BATTERY with 8KiB of RAM.
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0147] = 0x03;
+    rom[0x0149] = 0x02;
+    let mut cartridge = Cartridge::from_rom(rom.clone()).unwrap();
+    assert!(cartridge.has_battery());
+    cartridge.save_path = Some(save_path.clone());
+
+    let mut emu = GameboyEmulator::new(cartridge);
+    emu.bus.mbc.write_rom(0x0000, 0x0A);
+    emu.bus.mbc.write_ram(0xA123, 0x42);
+    emu.flush_save().unwrap();
+    assert_eq!(std::fs::read(&save_path).unwrap().len(), 0x2000);
+
+    let mut cartridge = Cartridge::from_rom(rom.clone()).unwrap();
+    cartridge.save_path = Some(save_path.clone());
+    let emu = GameboyEmulator::new(cartridge);
+    assert_eq!(emu.bus.mbc.get_ram()[0x0123], 0x42);
+    std::fs::remove_file(&save_path).unwrap();
+
+    // ? Save files which can't be read or written are reported as events rather than printed.
+    let mut cartridge = Cartridge::from_rom(rom).unwrap();
+    cartridge.save_path = Some(std::env::temp_dir());
+    let mut emu = GameboyEmulator::new(cartridge);
+    assert!(matches!(
+        emu.poll_event(),
+        Some(EmulatorEvent::SaveLoadFailed(_))
+    ));
+    emu.save_flush_timer = Some(0);
+    emu.update(JoypadState::default());
+    assert!(matches!(
+        emu.poll_event(),
+        Some(EmulatorEvent::SaveFailed(_))
+    ));
+}
+
+#[test]
+#[cfg(test)]
+fn save_state_round_trip() {
+    use crate::gb::{
+        cartridge::Cartridge, emu::GameboyEmulator, save_state::SAVE_STATE_MAGIC, utils::*,
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.cpu.set_register_pair(RegisterPair::PC, 0x1234);
+    emu.cpu.set_register(Register::A, 0x56);
+    emu.ime = IME::Enabled;
+    emu.bus.wram[0x0100] = 0x78;
+    emu.io_registers.timer.TIMA = 0x9A;
+    let state = emu.save_state().unwrap();
+    assert_eq!(state[0..4], SAVE_STATE_MAGIC);
+
+    let mut loaded = GameboyEmulator::new(Cartridge::new_empty());
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.cpu.get_register_pair(RegisterPair::PC), 0x1234);
+    assert_eq!(loaded.cpu.get_register(Register::A), 0x56);
+    assert_eq!(loaded.ime, IME::Enabled);
+    assert_eq!(loaded.bus.wram[0x0100], 0x78);
+    assert_eq!(loaded.io_registers.timer.TIMA, 0x9A);
+
+    // ? Corrupt or truncated states are rejected rather than partially loaded.
+    assert!(loaded.load_state(&state[..state.len() / 2]).is_err());
+    assert!(loaded.load_state(b"NOPE").is_err());
+}
+
+#[test]
+#[cfg(test)]
+fn ppu_mode_timing() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::graphics::{PPUMode, PPU},
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.io_registers.graphics.LCDC = 0b1000_0000;
+    emu.io_registers.graphics.LYC = 0x02;
+    // ? LYC and mode 0 STAT interrupt sources.
+    emu.io_registers.graphics.write_STAT(0b0100_1000);
+
+    let step = |emu: &mut GameboyEmulator, dots: usize| {
+        for _ in 0..dots / 4 {
+            PPU::render_step(emu);
+        }
+    };
+
+    // ? Line 0 starts in HBlank after turning the LCD on, then follows modes 2 -> 3 -> 0.
+    step(&mut emu, 456);
+    assert_eq!(emu.io_registers.graphics.LY, 1);
+    assert_eq!(emu.ppu.mode, PPUMode::OAMScan);
+    step(&mut emu, 80);
+    assert_eq!(emu.ppu.mode, PPUMode::Drawing);
+    emu.io_registers.interrupts.IF = 0x00;
+    step(&mut emu, 172);
+    assert_eq!(emu.ppu.mode, PPUMode::HBlank);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b0000_0010);
+
+    // ? The LYC source is blocked by the mode 0 source which is already high.
+    emu.io_registers.interrupts.IF = 0x00;
+    step(&mut emu, 456 - 252);
+    assert_eq!(emu.io_registers.graphics.LY, 2);
+    assert_eq!(emu.io_registers.graphics.STAT & 0b0000_0111, 0b0000_0110);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b0000_0000);
+
+    step(&mut emu, 456 * 142);
+    assert_eq!(emu.io_registers.graphics.LY, 144);
+    assert_eq!(emu.ppu.mode, PPUMode::VBlank);
+    assert_eq!(emu.io_registers.interrupts.IF & 0b0000_0001, 0b0000_0001);
+
+    step(&mut emu, 456 * 10);
+    assert_eq!(emu.io_registers.graphics.LY, 0);
+    assert_eq!(emu.ppu.mode, PPUMode::OAMScan);
+}
+
+#[test]
+#[cfg(test)]
+fn scanline_rendering() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{graphics::ORIGINAL_PALETTE, scanline::render_scanline},
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.io_registers.graphics.LCDC = 0b1001_0011;
+    emu.io_registers.graphics.BGP = 0b11_10_01_00;
+    emu.io_registers.graphics.OBP0 = 0b11_10_01_00;
+
+    // ? Tile 1 is solid color 1, tile 2 is solid color 3.
+    for row in 0..8 {
+        emu.bus.vram[0x10 + row * 2] = 0xFF;
+        emu.bus.vram[0x20 + row * 2] = 0xFF;
+        emu.bus.vram[0x20 + row * 2 + 1] = 0xFF;
+    }
+    emu.bus.vram[0x1800] = 0x01;
+
+    // ? Object at screen X 4 behind BG colors 1-3.
+    emu.bus.oam[0] = 16;
+    emu.bus.oam[1] = 8 + 4;
+    emu.bus.oam[2] = 0x02;
+    emu.bus.oam[3] = 0b1000_0000;
+
+    render_scanline(&mut emu);
+    let line = &emu.frame_buffer()[0..16];
+    assert!(line[0..8].iter().all(|&p| p == ORIGINAL_PALETTE[1]));
+    assert!(line[8..12].iter().all(|&p| p == ORIGINAL_PALETTE[3]));
+    assert!(line[12..16].iter().all(|&p| p == ORIGINAL_PALETTE[0]));
+
+    // ? The window covers the BG from WX - 7, starting at its own line 0.
+    emu.io_registers.graphics.LCDC |= 0b0010_0000;
+    emu.io_registers.graphics.WX = 7 + 2;
+    emu.io_registers.graphics.SCY = 8;
+    emu.bus.oam[0] = 0;
+    render_scanline(&mut emu);
+    let line = &emu.frame_buffer()[0..16];
+    assert!(line[0..2].iter().all(|&p| p == ORIGINAL_PALETTE[0]));
+    assert!(line[2..10].iter().all(|&p| p == ORIGINAL_PALETTE[1]));
+    assert_eq!(emu.ppu.window_line, 1);
+}
+
+#[test]
+#[cfg(test)]
+fn pixel_fifo_rendering() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::graphics::{PPUMode, Renderer, ORIGINAL_PALETTE, PPU, SCREEN_WIDTH},
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.ppu.renderer = Renderer::PixelFIFO;
+    emu.io_registers.graphics.LCDC = 0b1001_0011;
+    emu.io_registers.graphics.BGP = 0b11_10_01_00;
+    emu.io_registers.graphics.SCX = 3;
+
+    // ? Every BG tile is solid color 1.
+    for row in 0..8 {
+        emu.bus.vram[row * 2] = 0xFF;
+    }
+
+    // ? Change BGP halfway through drawing the next line.
+    let mut drawing_dots = 0;
+    while emu.ppu.mode != PPUMode::Drawing {
+        PPU::render_step(&mut emu);
+    }
+    let ly = emu.io_registers.graphics.LY as usize;
+    while emu.ppu.mode == PPUMode::Drawing {
+        if emu.ppu.fifo.lx >= 80 {
+            emu.io_registers.graphics.BGP = 0b11_10_11_00;
+        }
+        PPU::render_step(&mut emu);
+        drawing_dots += 4;
+    }
+    let line = &emu.frame_buffer()[ly * SCREEN_WIDTH..(ly + 1) * SCREEN_WIDTH];
+    assert!(line[0..80].iter().all(|&p| p == ORIGINAL_PALETTE[1]));
+    assert!(line[84..].iter().all(|&p| p == ORIGINAL_PALETTE[3]));
+
+    // ? Mode 3 is lengthened by the discarded SCX pixels.
+    assert!((172..=180).contains(&drawing_dots));
+
+    // ? Switching renderer partway through mode 3 only takes effect from the next line.
+    emu.ppu.renderer = Renderer::Scanline;
+    while emu.ppu.mode != PPUMode::Drawing {
+        PPU::render_step(&mut emu);
+    }
+    PPU::render_step(&mut emu);
+    emu.ppu.renderer = Renderer::PixelFIFO;
+    let ly = emu.io_registers.graphics.LY;
+    let mut drawing_dots = 4;
+    while emu.ppu.mode == PPUMode::Drawing {
+        PPU::render_step(&mut emu);
+        drawing_dots += 4;
+    }
+    assert_eq!(drawing_dots, 172);
+    while emu.io_registers.graphics.LY == ly || emu.ppu.mode != PPUMode::HBlank {
+        PPU::render_step(&mut emu);
+    }
+    assert_eq!(emu.ppu.fifo.lx as usize, SCREEN_WIDTH);
+}
+
+#[test]
+#[cfg(test)]
+fn apu_channels() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{audio::APU, timer::TimerRegisters},
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    let audio = &mut emu.io_registers.audio;
+    // ? Turn off the APU the boot ROM left on.
+    audio.write(0x0026, 0x00);
+
+    // ? Registers are ignored while the APU is off.
+    audio.write(0x0017, 0xF0);
+    assert_eq!(audio.read(0x0017), 0x00);
+    audio.write(0x0026, 0x80);
+    audio.write(0x0024, 0x77);
+    audio.write(0x0025, 0x02);
+
+    // ? Channel 2: 50% duty, length 62 (2 length clocks), full volume, ~1kHz.
+    audio.write(0x0016, 0b1000_0000 | 62);
+    audio.write(0x0017, 0xF0);
+    audio.write(0x0018, 0x06);
+    audio.write(0x0019, 0b1100_0111);
+    assert_eq!(audio.read(0x0026), 0b1111_0010);
+
+    emu.apu.sample_rate = Some(48000);
+    let mut peak: f32 = 0.0;
+    for _ in 0..4096 {
+        APU::update(&mut emu);
+    }
+    let samples = emu.take_samples();
+    assert!((186..=188).contains(&samples.len()));
+    for sample in samples {
+        assert_eq!(sample.left, 0.0);
+        peak = peak.max(sample.right.abs());
+    }
+    assert!(peak > 0.1);
+
+    // ? Out of range sample rates are clamped rather than overflowing.
+    emu.apu.sample_rate = Some(u32::MAX);
+    for _ in 0..4096 {
+        APU::update(&mut emu);
+    }
+    assert_eq!(emu.take_samples().len(), 4096);
+    emu.apu.sample_rate = Some(0);
+    APU::update(&mut emu);
+
+    // ? The frame sequencer is clocked every 8192 t-cycles by DIV, the length clocks on steps 0 and 2.
+    for _ in 0..(8192 / 4) {
+        TimerRegisters::update(&mut emu);
+    }
+    assert!(emu.io_registers.audio.channel2.enabled);
+    for _ in 0..(8192 / 4) * 2 {
+        TimerRegisters::update(&mut emu);
+    }
+    assert!(!emu.io_registers.audio.channel2.enabled);
+    assert_eq!(emu.io_registers.audio.read(0x0026), 0b1111_0000);
+
+    // ? Turning the APU off clears its registers.
+    emu.io_registers.audio.write(0x0026, 0x00);
+    assert_eq!(emu.io_registers.audio.read(0x0024), 0x00);
+}
+
+#[test]
+#[cfg(test)]
+fn wav_capture() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::{GameboyEmulator, M_CYCLES_PER_FRAME},
+        headless::RunLength,
+        io::audio::APU,
+        wav::WavSink,
+    };
+
+    assert_eq!(RunLength::Frames(3).get_frames(), 3);
+    assert_eq!(RunLength::Seconds(1.0).get_frames(), 60);
+
+    let wav_path = std::env::temp_dir().join("loki_emu_wav_capture.wav");
+    assert!(WavSink::create(&wav_path, 0, false).is_err());
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    let mut sink = WavSink::create(&wav_path, 32768, true).unwrap();
+    sink.attach(&mut emu);
+
+    // ? Channel 1 at full volume, only on the left.
+    let audio = &mut emu.io_registers.audio;
+    audio.write(0x0026, 0x00);
+    audio.write(0x0026, 0x80);
+    audio.write(0x0024, 0x77);
+    audio.write(0x0025, 0x10);
+    audio.write(0x0012, 0xF0);
+    audio.write(0x0014, 0x87);
+
+    for _ in 0..M_CYCLES_PER_FRAME {
+        APU::update(&mut emu);
+    }
+    sink.write(&mut emu).unwrap();
+    sink.finalize().unwrap();
+
+    let mut reader = hound::WavReader::open(&wav_path).unwrap();
+    assert_eq!(reader.spec().channels, 2);
+    assert_eq!(reader.spec().bits_per_sample, 16);
+    let samples: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples.len(), 548 * 2);
+    assert!(samples.chunks(2).any(|s| s[0] != 0));
+    assert!(samples.chunks(2).all(|s| s[1] == 0));
+
+    for n in 1..=4 {
+        let path = std::env::temp_dir().join(format!("loki_emu_wav_capture_ch{n}.wav"));
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.len(), 548);
+        let _ = std::fs::remove_file(path);
+    }
+    let _ = std::fs::remove_file(wav_path);
+}
+
+#[test]
+#[cfg(test)]
+fn serial_transfer() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{
+            serial::{SerialCapture, SerialRegisters},
+            timer::TimerRegisters,
+        },
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    let capture = SerialCapture::new();
+    emu.connect_serial(capture.clone());
+    emu.io_registers.timer.DIV = 0;
+
+    // ? Internal clock: 8 bits at 8192Hz, shifting in 0xFF from the capture.
+    emu.io_registers.serial.SB = b'A';
+    SerialRegisters::write_SC(&mut emu, 0x81);
+    assert_eq!(capture.get_text(), "A");
+    for _ in 0..(4096 / 4) - 1 {
+        TimerRegisters::update(&mut emu);
+    }
+    assert_eq!(emu.io_registers.serial.read_SC(), 0xFF);
+    assert_eq!(emu.io_registers.interrupts.IF & 0b1000, 0);
+    TimerRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.serial.read_SC(), 0x7F);
+    assert_eq!(emu.io_registers.serial.SB, 0xFF);
+    assert_eq!(emu.io_registers.interrupts.IF & 0b1000, 0b1000);
+
+    // ? External clock never finishes with nothing driving the clock.
+    emu.io_registers.interrupts.IF = 0;
+    SerialRegisters::write_SC(&mut emu, 0x80);
+    for _ in 0..4096 {
+        SerialRegisters::update(&mut emu);
+    }
+    assert_eq!(emu.io_registers.serial.read_SC(), 0xFE);
+    assert_eq!(emu.io_registers.interrupts.IF, 0);
+}
+
+#[test]
+#[cfg(test)]
+fn link_cable_transfer() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{
+            link::{LinkCable, TRANSFER_M_CYCLES},
+            serial::SerialRegisters,
+            timer::TimerRegisters,
+        },
+    };
+
+    let (port_a, port_b) = LinkCable::new();
+    let mut a = GameboyEmulator::new(Cartridge::new_empty());
+    let mut b = GameboyEmulator::new(Cartridge::new_empty());
+    a.connect_serial(port_a);
+    b.connect_serial(port_b);
+
+    // ? B waits for A's clock, the emulators are stepped in lockstep without running the CPUs.
+    let step = |a: &mut GameboyEmulator, b: &mut GameboyEmulator| {
+        for emu in [a, b] {
+            SerialRegisters::update(emu);
+            TimerRegisters::update(emu);
+        }
+    };
+    b.io_registers.serial.SB = 0x99;
+    SerialRegisters::write_SC(&mut b, 0x80);
+    step(&mut a, &mut b);
+    a.io_registers.serial.SB = 0x42;
+    SerialRegisters::write_SC(&mut a, 0x81);
+
+    for _ in 0..TRANSFER_M_CYCLES {
+        step(&mut a, &mut b);
+    }
+    for emu in [&a, &b] {
+        assert_eq!(emu.io_registers.serial.read_SC() & 0x80, 0x00);
+        assert_eq!(emu.io_registers.interrupts.IF & 0b1000, 0b1000);
+    }
+    assert_eq!(a.io_registers.serial.SB, 0x99);
+    assert_eq!(b.io_registers.serial.SB, 0x42);
+
+    // ? Once B stops waiting, A's transfers shift in 0xFF.
+    a.io_registers.serial.SB = 0x42;
+    SerialRegisters::write_SC(&mut a, 0x81);
+    for _ in 0..TRANSFER_M_CYCLES {
+        step(&mut a, &mut b);
+    }
+    assert_eq!(a.io_registers.serial.SB, 0xFF);
+
+    // ? The same protocol over a local socket.
+    #[cfg(unix)]
+    {
+        use crate::gb::{
+            emu::EmulatorEvent,
+            io::{
+                link::{LinkStream, SocketLink},
+                serial::Disconnected,
+            },
+        };
+        use std::{io::ErrorKind, os::unix::net::UnixStream};
+
+        let (stream_a, stream_b) = UnixStream::pair().unwrap();
+        a.connect_serial(SocketLink::new(LinkStream::Unix(stream_a)).unwrap());
+        b.connect_serial(SocketLink::new(LinkStream::Unix(stream_b)).unwrap());
+        b.io_registers.serial.SB = 0x12;
+        SerialRegisters::write_SC(&mut b, 0x80);
+        step(&mut a, &mut b);
+        a.io_registers.serial.SB = 0x34;
+        SerialRegisters::write_SC(&mut a, 0x81);
+        for _ in 0..TRANSFER_M_CYCLES {
+            step(&mut a, &mut b);
+        }
+        assert_eq!(a.io_registers.serial.SB, 0x12);
+        assert_eq!(b.io_registers.serial.SB, 0x34);
+        assert_eq!(b.io_registers.serial.read_SC() & 0x80, 0x00);
+
+        // ? A lost connection is reported as an event, after which transfers shift in 0xFF.
+        b.connect_serial(Disconnected);
+        for _ in 0..TRANSFER_M_CYCLES {
+            SerialRegisters::update(&mut a);
+        }
+        assert_eq!(
+            a.poll_event(),
+            Some(EmulatorEvent::LinkDisconnected(ErrorKind::UnexpectedEof))
+        );
+        SerialRegisters::write_SC(&mut a, 0x81);
+        for _ in 0..TRANSFER_M_CYCLES {
+            step(&mut a, &mut b);
+        }
+        assert_eq!(a.io_registers.serial.SB, 0xFF);
+        assert_eq!(a.poll_event(), None);
+    }
+}
+
+#[test]
+#[cfg(test)]
+fn printer_packets() {
+    use crate::gb::{
+        emu::EmulatorEvent,
+        io::{
+            printer::{status, GameBoyPrinter, PrintOutput},
+            serial::SerialPeer,
+        },
+    };
+    use std::io::ErrorKind;
+
+    // ? Sends a packet, returning the keep-alive and status bytes.
+    fn send(printer: &mut GameBoyPrinter, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![
+            command,
+            compressed as u8,
+            data.len() as u8,
+            (data.len() >> 8) as u8,
+        ];
+        packet.extend_from_slice(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        for byte in [0x88, 0x33].iter().chain(&packet) {
+            assert_eq!(printer.exchange(*byte), 0x00);
+        }
+        (printer.exchange(0x00), printer.exchange(0x00))
+    }
+
+    let directory = std::env::temp_dir().join("loki_emu_printer_packets");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut printer = GameBoyPrinter::new(Some(PrintOutput {
+        directory: directory.clone(),
+        extension: "pgm",
+    }));
+
+    assert_eq!(send(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+    // ? One band of 40 tiles: the top tile row is color 3 (a run of 0xFF), the bottom is color 1.
+    let mut band = Vec::new();
+    for _ in 0..5 {
+        band.extend_from_slice(&[0x80 | (64 - 2), 0xFF]);
+    }
+    for _ in 0..160 {
+        band.extend_from_slice(&[0x01, 0xFF, 0x00]);
+    }
+    let (_, status) = send(&mut printer, 0x04, true, &band);
+    assert_eq!(status, status::UNPROCESSED_DATA);
+    send(&mut printer, 0x04, false, &[]);
+
+    // ? Print with a margin of one line feed after, and color 1 mapped to light gray.
+    let (_, status) = send(
+        &mut printer,
+        0x02,
+        false,
+        &[0x01, 0x01, 0b11_10_01_00, 0x40],
+    );
+    assert_eq!(status, status::PRINTING);
+    let images = printer.get_images();
+    assert_eq!(images.len(), 1);
+    assert_eq!((images[0].width, images[0].height), (160, 24));
+    assert_eq!(images[0].pixels[0], 0x00);
+    assert_eq!(images[0].pixels[160 * 8], 0xAA);
+    assert_eq!(images[0].pixels[160 * 16], 0xFF);
+    let pgm = std::fs::read(directory.join("print_0000.pgm")).unwrap();
+    assert!(pgm.starts_with(b"P5\n160 24\n255\n"));
+    assert_eq!(printer.poll_event(), None);
+
+    // ? Printing finishes after a few status requests, bad checksums are reported.
+    while send(&mut printer, 0x0F, false, &[]).1 & status::PRINTING != 0 {}
+    printer.exchange(0x88);
+    printer.exchange(0x33);
+    for byte in [0x0F, 0x00, 0x00, 0x00, 0xFF, 0xFF] {
+        printer.exchange(byte);
+    }
+    assert_eq!(printer.exchange(0x00), 0x81);
+    assert_eq!(printer.exchange(0x00), status::CHECKSUM_ERROR);
+
+    // ? Prints which can't be saved are still kept, and reported as an event.
+    std::fs::remove_dir_all(&directory).unwrap();
+    send(&mut printer, 0x02, false, &[0x01, 0x00, 0x00, 0x40]);
+    assert_eq!(printer.get_images().len(), 2);
+    assert_eq!(
+        printer.poll_event(),
+        Some(EmulatorEvent::PrintSaveFailed(ErrorKind::NotFound))
+    );
+    assert_eq!(printer.poll_event(), None);
+}
+
+#[test]
+#[cfg(test)]
+fn halt_wake_and_bug() {
+    use crate::gb::{
+        cartridge::Cartridge, emu::GameboyEmulator, instructions::instructions::Instruction,
+        io::joypad::JoypadState, utils::*,
+    };
+
+    fn halt(emu: &mut GameboyEmulator) {
+        let mut instruction = Instruction::from(0x76);
+        instruction.step(emu);
+    }
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.ime = IME::Disabled;
+    emu.io_registers.interrupts.IE = 0b0100;
+
+    // ? Timers keep running while halted, and a pending interrupt wakes the CPU even with IME disabled.
+    emu.io_registers.timer.TAC = 0b101;
+    emu.io_registers.timer.TIMA = 0xFF;
+    halt(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Halted);
+    let pc = emu.cpu.get_register_pair(RegisterPair::PC);
+    emu.io_registers.timer.DIV = 0;
+    let mut m_cycles = 0;
+    while emu.cpu_state == CPUState::Halted && m_cycles < 16 {
+        assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), pc);
+        emu.update(JoypadState::default());
+        m_cycles += 1;
+    }
+    // ? TIMA increments every 16 t-cycles, overflowing on the 4th m-cycle.
+    assert_eq!(m_cycles, 4);
+    assert_eq!(emu.io_registers.timer.TIMA, 0x00);
+    assert_eq!(emu.io_registers.interrupts.IF & 0b0100, 0b0100);
+    assert_eq!(emu.ime, IME::Disabled);
+
+    // ? HALT bug: with an interrupt already pending HALT doesn't halt, and PC isn't incremented by the next fetch.
+    emu.finish_instruction(JoypadState::default());
+    halt(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Running);
+    let pc = emu.cpu.get_register_pair(RegisterPair::PC);
+    emu.update(JoypadState::default());
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), pc);
+    emu.update(JoypadState::default());
+    assert_eq!(
+        emu.cpu.get_register_pair(RegisterPair::PC),
+        pc.wrapping_add(1)
+    );
+}
+
+#[test]
+#[cfg(test)]
+fn stop_mode() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        instructions::instructions::Instruction,
+        io::joypad::{JoypadRegisters, JoypadState},
+        utils::*,
+    };
+
+    fn stop(emu: &mut GameboyEmulator) {
+        let mut instruction = Instruction::from(0x10);
+        while !instruction.has_completed() {
+            instruction.step(emu);
+        }
+    }
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.io_registers.joypad.write(0b0010_0000);
+    emu.io_registers.timer.DIV = 0x1234;
+
+    // ? No button held: DIV is reset, the padding byte is skipped and only a selected button press wakes the CPU.
+    let pc = emu.cpu.get_register_pair(RegisterPair::PC);
+    stop(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Stopped);
+    assert_eq!(emu.io_registers.timer.DIV, 0);
+    assert_eq!(
+        emu.cpu.get_register_pair(RegisterPair::PC),
+        pc.wrapping_add(1)
+    );
+    let ly = emu.io_registers.graphics.LY;
+    let pressed = JoypadState {
+        a: true,
+        ..Default::default()
+    };
+    for _ in 0..1000 {
+        emu.update(pressed);
+    }
+    assert_eq!(emu.cpu_state, CPUState::Stopped);
+    assert_eq!(emu.io_registers.timer.DIV, 0);
+    assert_eq!(emu.io_registers.graphics.LY, ly);
+    let pressed = JoypadState {
+        right: true,
+        ..Default::default()
+    };
+    emu.update(pressed);
+    assert_eq!(emu.cpu_state, CPUState::Running);
+
+    // ? A button held with an interrupt pending: STOP is a 1-byte NOP.
+    emu.finish_instruction(pressed);
+    JoypadRegisters::update(&mut emu, pressed);
+    emu.io_registers.interrupts.IE = 0b1_0000;
+    emu.io_registers.interrupts.IF = 0b1_0000;
+    let (pc, div) = (
+        emu.cpu.get_register_pair(RegisterPair::PC),
+        emu.io_registers.timer.DIV,
+    );
+    stop(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Running);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), pc);
+    assert_eq!(emu.io_registers.timer.DIV, div);
+
+    // ? A button held without an interrupt pending: STOP halts as a 2-byte opcode.
+    emu.io_registers.interrupts.IF = 0;
+    stop(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Halted);
+    assert_eq!(
+        emu.cpu.get_register_pair(RegisterPair::PC),
+        pc.wrapping_add(1)
+    );
+}
+
+#[test]
+#[cfg(test)]
+fn interrupt_dispatch() {
+    use crate::gb::{
+        cartridge::Cartridge, emu::GameboyEmulator, instructions::instructions::Instruction,
+        io::joypad::JoypadState, utils::*,
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.cpu.set_register_pair(RegisterPair::PC, 0x0200);
+    emu.cpu.set_register_pair(RegisterPair::SP, 0xD000);
+    emu.io_registers.interrupts.IE = 0b1100;
+    emu.io_registers.interrupts.IF = 0b1100;
+
+    // ? `EI` is delayed by one instruction.
+    let mut instruction = Instruction::from(0xFB);
+    instruction.step(&mut emu);
+    assert_eq!(emu.ime, IME::Scheduled);
+    emu.update(JoypadState::default());
+    assert_eq!(emu.ime, IME::Enabled);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0201);
+
+    // ? Dispatch takes 5 m-cycles, the highest priority interrupt (timer) is serviced first.
+    for _ in 0..4 {
+        emu.update(JoypadState::default());
+        assert_eq!(emu.ime, IME::Disabled);
+        assert_ne!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0050);
+    }
+    emu.update(JoypadState::default());
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0050);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::SP), 0xCFFE);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b1000);
+
+    // ? `RETI` enables interrupts immediately, so the serial interrupt is dispatched straight after it.
+    let mut instruction = Instruction::from(0xD9);
+    while !instruction.has_completed() {
+        instruction.step(&mut emu);
+    }
+    assert_eq!(emu.ime, IME::Enabled);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0201);
+    for _ in 0..5 {
+        emu.update(JoypadState::default());
+    }
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0058);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b0000);
+
+    // ? Pushing `PC`'s high byte to `0xFFFF` overwrites `IE`, if that leaves nothing pending the dispatch jumps to `0x0000`.
+    emu.ime = IME::Enabled;
+    emu.cpu.set_register_pair(RegisterPair::PC, 0x00C0);
+    emu.cpu.set_register_pair(RegisterPair::SP, 0x0000);
+    emu.io_registers.interrupts.IE = 0b0001;
+    emu.io_registers.interrupts.IF = 0b0001;
+    for _ in 0..5 {
+        emu.update(JoypadState::default());
+    }
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0000);
+    assert_eq!(emu.io_registers.interrupts.IE, 0x00);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b0001);
+
+    // ? The dispatch still goes ahead if the interrupt is still enabled after the push.
+    emu.ime = IME::Enabled;
+    emu.cpu.set_register_pair(RegisterPair::PC, 0x01C0);
+    emu.cpu.set_register_pair(RegisterPair::SP, 0x0000);
+    emu.io_registers.interrupts.IE = 0b0001;
+    for _ in 0..5 {
+        emu.update(JoypadState::default());
+    }
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0040);
+    assert_eq!(emu.io_registers.interrupts.IF, 0b0000);
+}
+
+#[test]
+#[cfg(test)]
+fn invalid_opcode_lockup() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::{EmulatorEvent, GameboyEmulator},
+        instructions::instructions::Instruction,
+        io::joypad::JoypadState,
+        utils::*,
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.cpu.set_register_pair(RegisterPair::PC, 0x0201);
+    let mut instruction = Instruction::from(0xDD);
+    instruction.step(&mut emu);
+    assert_eq!(emu.cpu_state, CPUState::Locked);
+    assert_eq!(
+        emu.poll_event(),
+        Some(EmulatorEvent::IllegalOpcode {
+            pc: 0x0200,
+            opcode: 0xDD
+        })
+    );
+
+    // ? Nothing wakes the CPU, but the rest of the system keeps running.
+    emu.ime = IME::Enabled;
+    emu.io_registers.interrupts.IE = 0b1_1111;
+    emu.io_registers.interrupts.IF = 0b1_1111;
+    emu.io_registers.graphics.LCDC = 0x91;
+    let ly = emu.io_registers.graphics.LY;
+    for _ in 0..1000 {
+        emu.update(JoypadState::default());
+    }
+    assert_eq!(emu.cpu_state, CPUState::Locked);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0201);
+    assert_ne!(emu.io_registers.graphics.LY, ly);
+}
+
+#[test]
+#[cfg(test)]
+fn io_open_bus() {
+    use crate::gb::{
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::io_registers::{IORegisters, OPEN_BUS},
+    };
+
+    // ? (first address, last address, value read back after writing 0x00), every address is read and written.
+    let table: [(u16, u16, u8); 13] = [
+        (0xFF03, 0xFF03, OPEN_BUS),
+        (0xFF07, 0xFF07, 0b1111_1000),
+        (0xFF08, 0xFF0E, OPEN_BUS),
+        (0xFF0F, 0xFF0F, 0b1110_0000),
+        (0xFF13, 0xFF13, OPEN_BUS),
+        (0xFF15, 0xFF15, OPEN_BUS),
+        (0xFF18, 0xFF18, OPEN_BUS),
+        (0xFF1D, 0xFF1D, OPEN_BUS),
+        (0xFF1F, 0xFF1F, OPEN_BUS),
+        (0xFF27, 0xFF2F, OPEN_BUS),
+        (0xFF4C, 0xFF4F, OPEN_BUS),
+        (0xFF50, 0xFF7F, OPEN_BUS),
+        (0xFFFF, 0xFFFF, 0b1110_0000),
+    ];
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    for address in 0xFF00..=0xFFFFu16 {
+        // ? HRAM is handled by the bus itself.
+        if (0xFF80..=0xFFFE).contains(&address) {
+            continue;
+        }
+        let index = address as usize - 0xFF00;
+        IORegisters::read(&mut emu, index);
+        IORegisters::write(&mut emu, index, 0x00);
+        let value = IORegisters::read(&mut emu, index);
+        if let Some(&(_, _, expected)) = table
+            .iter()
+            .find(|(first, last, _)| (*first..=*last).contains(&address))
+        {
+            assert_eq!(value, expected, "{address:#06X}");
+        }
+    }
+}
+
+#[test]
+#[cfg(test)]
+fn post_boot_state() {
+    use crate::gb::{
+        boot::Model, bus::Bus, cartridge::Cartridge, emu::GameboyEmulator,
+        io::io_registers::IORegisters, utils::*,
+    };
+
+    let emu = GameboyEmulator::new(Cartridge::new_empty());
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::AF), 0x0180);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::BC), 0x0013);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::DE), 0x00D8);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::HL), 0x014D);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::SP), 0xFFFE);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0100);
+
+    let mut emu = GameboyEmulator::new_with_model(Cartridge::new_empty(), Model::CGB, None);
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::AF), 0x1180);
+    for (index, value) in [
+        (0x00, 0xCF),
+        (0x02, 0x7F),
+        (0x07, 0xF8),
+        (0x0F, 0xE1),
+        (0x10, 0x80),
+        (0x11, 0xBF),
+        (0x12, 0xF3),
+        (0x24, 0x77),
+        (0x25, 0xF3),
+        (0x26, 0xF1),
+        (0x40, 0x91),
+        (0x46, 0xFF),
+        (0x47, 0xFC),
+        (0xFF, 0xE0),
+    ] {
+        assert_eq!(IORegisters::read(&mut emu, index), value, "{index:#04X}");
+    }
+
+    // ? With a boot ROM everything starts from zero.
+    let mut emu =
+        GameboyEmulator::new_with_model(Cartridge::new_empty(), Model::DMG, Some(vec![0x31; 256]));
+    assert_eq!(emu.cpu.get_register_pair(RegisterPair::PC), 0x0000);
+    assert_eq!(emu.io_registers.boot_rom_control, 0x00);
+    assert_eq!(Bus::read(&mut emu, 0x0000), 0x31);
+
+    // ? Writing to 0xFF50 unmaps the boot ROM for good.
+    Bus::write(&mut emu, 0xFF50, 0x01);
+    assert_eq!(Bus::read(&mut emu, 0x0000), 0x00);
+    Bus::write(&mut emu, 0xFF50, 0x00);
+    assert_eq!(Bus::read(&mut emu, 0x0000), 0x00);
+    assert_eq!(Bus::read(&mut emu, 0xFF50), 0xFF);
+}
+
+#[test]
+#[cfg(test)]
+fn cgb_mode() {
+    use crate::gb::{
+        boot::Model,
+        bus::Bus,
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        instructions::instructions::Instruction,
+        io::{
+            graphics::{GraphicsRegisters, PPUMode},
+            hdma::HDMARegisters,
+            io_registers::IORegisters,
+        },
+    };
+
+    // ? DMG games run in compatibility mode, where the CGB registers are unmapped.
+    let mut emu = GameboyEmulator::new_with_model(Cartridge::new_empty(), Model::CGB, None);
+    assert!(!emu.is_cgb_mode());
+    assert_eq!(IORegisters::read(&mut emu, 0x4F), 0xFF);
+
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0143] = 0x80;
+    let cartridge = Cartridge::from_rom(rom).unwrap();
+    let mut emu = GameboyEmulator::new_with_model(cartridge, Model::CGB, None);
+    assert!(emu.is_cgb_mode());
+    assert_eq!(IORegisters::read(&mut emu, 0x4F), 0xFE);
+    assert_eq!(
+        emu.io_registers.graphics.bg_palette_ram.get_color(0, 0),
+        0xFFFFFF
+    );
+
+    // ? Palette RAM writes auto-increment the index, wrapping around after the last byte.
+    IORegisters::write(&mut emu, 0x68, 0x80 | 0x3E);
+    IORegisters::write(&mut emu, 0x69, 0x1F);
+    IORegisters::write(&mut emu, 0x69, 0x00);
+    IORegisters::write(&mut emu, 0x69, 0xE0);
+    assert_eq!(IORegisters::read(&mut emu, 0x68), 0xC1);
+    assert_eq!(
+        emu.io_registers.graphics.bg_palette_ram.get_color(7, 3),
+        0xFF0000
+    );
+    assert_eq!(emu.io_registers.graphics.bg_palette_ram[0], 0xE0);
+
+    // ? General purpose DMA copies straight into the selected VRAM bank.
+    for i in 0..0x20 {
+        emu.bus.wram[i] = i as u8;
+    }
+    IORegisters::write(&mut emu, 0x4F, 0x01);
+    for (index, value) in [(0x51, 0xC0), (0x52, 0x00), (0x53, 0x01), (0x54, 0x00)] {
+        IORegisters::write(&mut emu, index, value);
+    }
+    IORegisters::write(&mut emu, 0x55, 0x01);
+    assert_eq!(IORegisters::read(&mut emu, 0x55), 0xFF);
+    for i in 0..0x20 {
+        assert_eq!(emu.bus.vram[0x2100 + i], i as u8);
+        assert_eq!(emu.bus.vram[0x0100 + i], 0x00);
+    }
+
+    // ? The CPU sees the same VRAM bank, and SVBK selects the WRAM bank at 0xD000 (0 selecting bank 1).
+    emu.ppu.mode = PPUMode::HBlank;
+    assert_eq!(Bus::read(&mut emu, 0x8101), 0x01);
+    IORegisters::write(&mut emu, 0x4F, 0x00);
+    assert_eq!(Bus::read(&mut emu, 0x8101), 0x00);
+    IORegisters::write(&mut emu, 0x70, 0x03);
+    Bus::write(&mut emu, 0xD000, 0xAA);
+    assert_eq!(emu.bus.wram[0x3000], 0xAA);
+    assert_eq!(Bus::read(&mut emu, 0xF000), 0xAA);
+    IORegisters::write(&mut emu, 0x70, 0x00);
+    Bus::write(&mut emu, 0xD000, 0xBB);
+    assert_eq!(emu.bus.wram[0x1000], 0xBB);
+    assert_eq!(Bus::read(&mut emu, 0xD000), 0xBB);
+    IORegisters::write(&mut emu, 0x70, 0x03);
+    assert_eq!(Bus::read(&mut emu, 0xD000), 0xAA);
+    assert_eq!(Bus::read(&mut emu, 0xC001), 0x01);
+
+    // ? HBlank DMA copies a block per HBlank until it finishes or is cancelled.
+    emu.ppu.mode = PPUMode::Drawing;
+    IORegisters::write(&mut emu, 0x55, 0x82);
+    assert_eq!(IORegisters::read(&mut emu, 0x55), 0x02);
+    HDMARegisters::hblank(&mut emu);
+    assert_eq!(IORegisters::read(&mut emu, 0x55), 0x01);
+    IORegisters::write(&mut emu, 0x55, 0x00);
+    assert_eq!(IORegisters::read(&mut emu, 0x55), 0x81);
+    HDMARegisters::hblank(&mut emu);
+    assert_eq!(IORegisters::read(&mut emu, 0x55), 0x81);
+
+    // ? VRAM DMA reads its source directly, ignoring an active OAM DMA on the same bus.
+    for i in 0..0xA0 {
+        emu.bus.wram[0x100 + i] = 0xCC;
+    }
+    IORegisters::write(&mut emu, 0x46, 0xC1);
+    for _ in 0..12 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    assert_eq!(Bus::read(&mut emu, 0xC000), 0xCC);
+    emu.ppu.mode = PPUMode::HBlank;
+    for (index, value) in [(0x51, 0xC0), (0x52, 0x00), (0x53, 0x02), (0x54, 0x00)] {
+        IORegisters::write(&mut emu, index, value);
+    }
+    IORegisters::write(&mut emu, 0x55, 0x01);
+    for i in 0..0x20 {
+        assert_eq!(emu.bus.vram[0x0200 + i], i as u8);
+    }
+
+    // ? Sources from 0xE000 up read cartridge RAM rather than echo RAM.
+    while emu.io_registers.graphics.DMA_transfer_progress.is_some() {
+        GraphicsRegisters::update(&mut emu);
+    }
+    let cartridge_ram: Vec<u8> = (0..0x10).map(|i| Bus::read(&mut emu, 0xA000 + i)).collect();
+    for (index, value) in [(0x51, 0xE0), (0x52, 0x00), (0x53, 0x03), (0x54, 0x00)] {
+        IORegisters::write(&mut emu, index, value);
+    }
+    IORegisters::write(&mut emu, 0x55, 0x00);
+    for (i, value) in cartridge_ram.into_iter().enumerate() {
+        assert_eq!(emu.bus.vram[0x0300 + i], value);
+        assert_ne!(emu.bus.vram[0x0300 + i], emu.bus.wram[i]);
+    }
+
+    // ? An armed speed switch happens on STOP instead of entering STOP mode.
+    IORegisters::write(&mut emu, 0x4D, 0x01);
+    assert_eq!(IORegisters::read(&mut emu, 0x4D), 0x7F);
+    let mut instruction = Instruction::from(0x10);
+    while !instruction.has_completed() {
+        instruction.step(&mut emu);
+    }
+    assert!(emu.is_double_speed());
+    assert_eq!(IORegisters::read(&mut emu, 0x4D), 0xFE);
+}
+
+#[test]
+#[cfg(test)]
+fn sgb_packets() {
+    use crate::gb::{
+        boot::Model,
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{
+            io_registers::IORegisters,
+            joypad::{JoypadRegisters, JoypadState},
+        },
+        sgb::{SGB, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH, SGB_SCREEN_X, SGB_SCREEN_Y},
+    };
+
+    fn send_packet(emu: &mut GameboyEmulator, packet: [u8; 16]) {
+        IORegisters::write(emu, 0x00, 0x00);
+        IORegisters::write(emu, 0x00, 0x30);
+        for i in 0..128 {
+            let bit = packet[i / 8] & (1 << (i % 8)) != 0;
+            IORegisters::write(emu, 0x00, if bit { 0x10 } else { 0x20 });
+            IORegisters::write(emu, 0x00, 0x30);
+        }
+        IORegisters::write(emu, 0x00, 0x20);
+        IORegisters::write(emu, 0x00, 0x30);
+    }
+
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x0146] = 0x03;
+    rom[0x014B] = 0x33;
+    let cartridge = Cartridge::from_rom(rom).unwrap();
+    let mut emu = GameboyEmulator::new_with_model(cartridge, Model::SGB, None);
+    let (frame, width, height) = emu.display();
+    assert_eq!(
+        (frame.len(), width, height),
+        (SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT, 256, 224)
+    );
+
+    // ? PAL01: shared color 0 = white, palette 0 = red shades, palette 1 = blue shades.
+    let mut packet = [0x00; 16];
+    packet[0] = 0x01;
+    for (i, color) in [0x7FFFu16, 0x001F, 0x0010, 0x0008, 0x7C00, 0x4000, 0x2000]
+        .into_iter()
+        .enumerate()
+    {
+        packet[1 + i * 2..3 + i * 2].copy_from_slice(&color.to_le_bytes());
+    }
+    send_packet(&mut emu, packet);
+    let sgb = emu.sgb.as_ref().unwrap();
+    assert_eq!(sgb.palettes[0], [0x7FFF, 0x001F, 0x0010, 0x0008]);
+    assert_eq!(sgb.palettes[1], [0x7FFF, 0x7C00, 0x4000, 0x2000]);
+    assert_eq!(sgb.palettes[3][0], 0x7FFF);
+
+    // ? ATTR_DIV: palette 1 to the right of column 10, palette 0 left of it and on it.
+    let mut packet = [0x00; 16];
+    packet[0] = 0x06 << 3 | 1;
+    packet[1] = 0b00_00_01;
+    packet[2] = 10;
+    send_packet(&mut emu, packet);
+    let sgb = emu.sgb.as_ref().unwrap();
+    assert_eq!(sgb.get_color(0, 0, 1), 0xFF0000);
+    assert_eq!(sgb.get_color(80, 0, 1), 0xFF0000);
+    assert_eq!(sgb.get_color(88, 143, 1), 0x0000FF);
+
+    // ? MLT_REQ: with two joypads, P1 reads the current joypad's ID, which advances as P15 is released.
+    let mut packet = [0x00; 16];
+    packet[0] = 0x11 << 3 | 1;
+    packet[1] = 0x01;
+    send_packet(&mut emu, packet);
+    JoypadRegisters::update(&mut emu, JoypadState::default());
+    assert_eq!(IORegisters::read(&mut emu, 0x00) & 0x0F, 0x0F);
+    IORegisters::write(&mut emu, 0x00, 0x10);
+    IORegisters::write(&mut emu, 0x00, 0x30);
+    JoypadRegisters::update(&mut emu, JoypadState::default());
+    assert_eq!(IORegisters::read(&mut emu, 0x00) & 0x0F, 0x0E);
+
+    // ? MASK_EN: a black screen inside the border.
+    let mut packet = [0x00; 16];
+    packet[0] = 0x17 << 3 | 1;
+    packet[1] = 0x02;
+    send_packet(&mut emu, packet);
+    emu.ppu.frame_buffer.fill(0x00FFFFFF);
+    SGB::compose_frame(&mut emu);
+    let (frame, ..) = emu.display();
+    assert_eq!(
+        frame[SGB_SCREEN_Y * SGB_SCREEN_WIDTH + SGB_SCREEN_X],
+        0x000000
+    );
+    assert_eq!(frame[0], 0xFFFFFF);
+
+    // ? Packets are ignored by games that don't support the SGB.
+    let mut emu = GameboyEmulator::new_with_model(Cartridge::new_empty(), Model::SGB, None);
+    send_packet(&mut emu, packet);
+    assert_eq!(emu.sgb.as_ref().unwrap().mask, 0);
+}
+
+#[test]
+#[cfg(test)]
+fn oam_dma() {
+    use crate::gb::{
+        bus::Bus,
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::{
+            graphics::{GraphicsRegisters, PPUMode},
+            io_registers::IORegisters,
+        },
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    for i in 0..0xA0 {
+        emu.bus.wram[0x100 + i] = i as u8;
+        emu.bus.wram[0x200 + i] = !(i as u8);
+    }
+
+    // ? 1 m-cycle of startup delay after the write, then 160 m-cycles of transfer.
+    IORegisters::write(&mut emu, 0x46, 0xC1);
+    GraphicsRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, None);
+    for _ in 0..160 {
+        GraphicsRegisters::update(&mut emu);
+        assert!(emu.io_registers.graphics.DMA_transfer_progress.is_some());
+    }
+    GraphicsRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, None);
+    for i in 0..0xA0 {
+        assert_eq!(emu.bus.oam[i], i as u8);
+    }
+
+    // ? Restarting mid-transfer keeps the old transfer going until the new one begins.
+    IORegisters::write(&mut emu, 0x46, 0xC1);
+    for _ in 0..51 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    IORegisters::write(&mut emu, 0x46, 0xC2);
+    GraphicsRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, Some(50));
+    GraphicsRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, Some(0));
+    for _ in 0..160 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, None);
+    for i in 0..0xA0 {
+        assert_eq!(emu.bus.oam[i], !(i as u8));
+    }
+
+    // ? While transferring from WRAM, the external bus sees the byte being transferred and OAM is blocked.
+    emu.ppu.mode = PPUMode::HBlank;
+    emu.bus.vram[0x0000] = 0x42;
+    emu.bus.hram[0x00] = 0x24;
+    IORegisters::write(&mut emu, 0x46, 0xC1);
+    for _ in 0..12 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    assert_eq!(emu.io_registers.graphics.DMA_transfer_progress, Some(10));
+    assert_eq!(Bus::read(&mut emu, 0xFE00), 0xFF);
+    assert_eq!(Bus::read(&mut emu, 0xC000), 10);
+    assert_eq!(Bus::read(&mut emu, 0x0000), 10);
+    assert_eq!(Bus::read(&mut emu, 0x8000), 0x42);
+    assert_eq!(Bus::read(&mut emu, 0xFF80), 0x24);
+    Bus::write(&mut emu, 0xC000, 0x99);
+    Bus::write(&mut emu, 0xFE00, 0x99);
+    Bus::write(&mut emu, 0x8001, 0x11);
+    Bus::write(&mut emu, 0xFF81, 0x22);
+    assert_eq!(emu.bus.wram[0x0000], 0x00);
+    assert_eq!(emu.bus.oam[0x00], 0x00);
+    assert_eq!(emu.bus.vram[0x0001], 0x11);
+    assert_eq!(emu.bus.hram[0x01], 0x22);
+
+    // ? While transferring from VRAM, only the VRAM bus is taken.
+    for _ in 0..160 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    IORegisters::write(&mut emu, 0x46, 0x80);
+    for _ in 0..2 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    assert_eq!(Bus::read(&mut emu, 0x9000), 0x42);
+    assert_eq!(Bus::read(&mut emu, 0xC101), 0x01);
+    Bus::write(&mut emu, 0x8002, 0x33);
+    Bus::write(&mut emu, 0xC000, 0x55);
+    assert_eq!(emu.bus.vram[0x0002], 0x00);
+    assert_eq!(emu.bus.wram[0x0000], 0x55);
+    for _ in 0..160 {
+        GraphicsRegisters::update(&mut emu);
+    }
+    assert_eq!(Bus::read(&mut emu, 0xFE00), 0x42);
+
+    // ? Sources from 0xE000 up read WRAM again.
+    IORegisters::write(&mut emu, 0x46, 0xFE);
+    GraphicsRegisters::update(&mut emu);
+    GraphicsRegisters::update(&mut emu);
+    assert_eq!(emu.io_registers.graphics.get_DMA_address(0x10), 0xDE10);
+}
+
+#[test]
+#[cfg(test)]
+fn ppu_access_restrictions() {
+    use crate::gb::{
+        bus::Bus,
+        cartridge::Cartridge,
+        emu::GameboyEmulator,
+        io::graphics::{PPUMode, PPU},
+    };
+
+    let mut emu = GameboyEmulator::new(Cartridge::new_empty());
+    emu.io_registers.graphics.LCDC = 0x91;
+    let modes = [
+        (PPUMode::HBlank, true, true),
+        (PPUMode::VBlank, true, true),
+        (PPUMode::OAMScan, true, false),
+        (PPUMode::Drawing, false, false),
+    ];
+    for (mode, vram, oam) in modes {
+        emu.ppu.mode = mode;
+        assert_eq!(PPU::can_access(&emu, 0x9FFF), vram);
+        assert_eq!(PPU::can_access(&emu, 0xFE9F), oam);
+        assert!(PPU::can_access(&emu, 0xC000));
+
+        // ? Blocked reads return 0xFF and blocked writes are dropped.
+        emu.bus.vram[0x0000] = 0x11;
+        emu.bus.oam[0x00] = 0x22;
+        assert_eq!(Bus::read(&mut emu, 0x8000), if vram { 0x11 } else { 0xFF });
+        assert_eq!(Bus::read(&mut emu, 0xFE00), if oam { 0x22 } else { 0xFF });
+        Bus::write(&mut emu, 0x8000, 0x33);
+        Bus::write(&mut emu, 0xFE00, 0x44);
+        assert_eq!(emu.bus.vram[0x0000], if vram { 0x33 } else { 0x11 });
+        assert_eq!(emu.bus.oam[0x00], if oam { 0x44 } else { 0x22 });
+    }
+
+    // ? Everything is accessible while the LCD is off, or with the restrictions turned off.
+    emu.io_registers.graphics.LCDC = 0x11;
+    Bus::write(&mut emu, 0x8000, 0x55);
+    Bus::write(&mut emu, 0xFE00, 0x66);
+    assert_eq!(Bus::read(&mut emu, 0x8000), 0x55);
+    assert_eq!(Bus::read(&mut emu, 0xFE00), 0x66);
+    emu.io_registers.graphics.LCDC = 0x91;
+    emu.ppu.access_restrictions = false;
+    Bus::write(&mut emu, 0x8000, 0x77);
+    Bus::write(&mut emu, 0xFE00, 0x88);
+    assert_eq!(Bus::read(&mut emu, 0x8000), 0x77);
+    assert_eq!(Bus::read(&mut emu, 0xFE00), 0x88);
+
+    // ? The toggle is a frontend option, so loading a state doesn't change it.
+    let state = emu.save_state().unwrap();
+    let mut loaded = GameboyEmulator::new(Cartridge::new_empty());
+    loaded.load_state(&state).unwrap();
+    assert!(loaded.ppu.access_restrictions);
+    emu.load_state(&state).unwrap();
+    assert!(!emu.ppu.access_restrictions);
+}
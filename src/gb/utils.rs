@@ -1,12 +1,26 @@
 use std::ops::{BitAnd, BitOr};
 
-#[derive(Debug, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IME {
     Disabled,
     Scheduled,
     Enabled,
 }
 
+/// What the CPU is doing between instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CPUState {
+    Running,
+    /// Set by `HALT`, woken by any pending interrupt (even with `IME` disabled).
+    Halted,
+    /// Set by `STOP`, everything but the cartridge is paused until a selected joypad line goes low.
+    Stopped,
+    /// Set by an invalid opcode, the CPU never executes anything again but the rest of the system keeps running.
+    Locked,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Register {
     A,
@@ -97,18 +111,15 @@ impl InterruptMask {
     }
 
     pub fn get_interrupt_from_register(register: u8) -> Option<Self> {
-        for interrupt in [
+        [
             Self::VBlank,
             Self::LCDStat,
             Self::Timer,
             Self::Serial,
             Self::Joypad,
-        ] {
-            if get_bit(register, interrupt) {
-                return Some(interrupt);
-            }
-        }
-        return None;
+        ]
+        .into_iter()
+        .find(|&interrupt| get_bit(register, interrupt))
     }
 }
 
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::path::Path;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use super::{emu::GameboyEmulator, io::audio::MAX_SAMPLE_RATE};
+
+type Writer = WavWriter<BufWriter<File>>;
+
+fn to_io_error(err: hound::Error) -> Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => Error::new(ErrorKind::InvalidData, err),
+    }
+}
+
+/// Converts a sample in the range `-1.0..=1.0` to 16-bit PCM.
+#[inline]
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Records the emulator's audio to 16-bit PCM WAV files, see [`GameboyEmulator::run_headless`].
+pub struct WavSink {
+    pub sample_rate: u32,
+    /// The stereo mix, as heard through the headphone jack.
+    mix: Writer,
+    /// Optional mono recordings of each channel before panning and volume.
+    channels: Option<Vec<Writer>>,
+}
+
+impl WavSink {
+    /// Creates a WAV file at `path` for the stereo mix, and with `per_channel` also `<name>_ch1.wav`..`<name>_ch4.wav` next to it.
+    pub fn create(
+        path: impl AsRef<Path>,
+        sample_rate: u32,
+        per_channel: bool,
+    ) -> std::io::Result<Self> {
+        if !(1..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("GB - Unsupported sample rate {sample_rate}"),
+            ));
+        }
+        let path = path.as_ref();
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mix = WavWriter::create(path, spec).map_err(to_io_error)?;
+
+        let channels = match per_channel {
+            true => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let spec = WavSpec {
+                    channels: 1,
+                    ..spec
+                };
+                let channels = (1..=4)
+                    .map(|n| {
+                        let path = path.with_file_name(format!("{stem}_ch{n}.wav"));
+                        WavWriter::create(path, spec).map_err(to_io_error)
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                Some(channels)
+            }
+            false => None,
+        };
+
+        Ok(Self {
+            sample_rate,
+            mix,
+            channels,
+        })
+    }
+
+    /// Sets the emulator's APU up to generate the samples this sink records.
+    pub fn attach(&self, emu: &mut GameboyEmulator) {
+        emu.apu.sample_rate = Some(self.sample_rate);
+        emu.apu.capture_channels = self.channels.is_some();
+    }
+
+    /// Writes every sample the emulator has generated since the last write.
+    pub fn write(&mut self, emu: &mut GameboyEmulator) -> std::io::Result<()> {
+        for sample in emu.take_samples() {
+            self.mix
+                .write_sample(to_pcm(sample.left))
+                .map_err(to_io_error)?;
+            self.mix
+                .write_sample(to_pcm(sample.right))
+                .map_err(to_io_error)?;
+        }
+        let channel_samples = emu.take_channel_samples();
+        if let Some(channels) = &mut self.channels {
+            for samples in channel_samples {
+                for (writer, sample) in channels.iter_mut().zip(samples) {
+                    writer.write_sample(to_pcm(sample)).map_err(to_io_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the WAV headers, which would otherwise only be written (ignoring errors) when dropped.
+    pub fn finalize(self) -> std::io::Result<()> {
+        self.mix.finalize().map_err(to_io_error)?;
+        for writer in self.channels.into_iter().flatten() {
+            writer.finalize().map_err(to_io_error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+pub mod byte_field;
+pub mod gb;
@@ -1,41 +1,310 @@
-// #![cfg(not(test))]
-pub mod byte_field;
-pub mod gb;
-
-use std::{num::NonZeroU32, rc::Rc, time::Instant};
+use std::{
+    num::NonZeroU32,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use gb::io::graphics::PPU;
-use softbuffer::{Buffer, Context, Surface};
+use loki_emu::gb::{
+    boot::{load_boot_rom, Model},
+    cartridge::Cartridge,
+    emu::{EmulatorEvent, GameboyEmulator},
+    headless::RunLength,
+    io::{
+        audio::MAX_SAMPLE_RATE,
+        graphics::Renderer,
+        joypad::JoypadState,
+        link::SocketLink,
+        printer::{GameBoyPrinter, PrintOutput},
+        serial::SerialCapture,
+    },
+    wav::WavSink,
+};
+use softbuffer::{Context, Surface};
 use winit::{
     dpi::PhysicalSize,
     error::EventLoopError,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    keyboard::KeyCode,
+    window::WindowBuilder,
 };
 use winit_input_helper::WinitInputHelper;
 
-use crate::gb::{
-    bus::{Bus, HRAM, WRAM},
-    cartridge::Cartridge,
-    cpu::CPU,
-    emu::GameboyEmulator,
-    instructions::instructions::Instruction,
-    io::{
-        graphics::{OAM, VRAM},
-        io_registers::IORegisters,
-    },
-    utils::IME,
-};
+/// The DMG's refresh rate is ~59.73Hz.
+const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+
+const USAGE: &str = "Usage: loki_emu [--model MODEL] [--force-dmg] [--boot-rom PATH] [--pixel-fifo] [--no-access-restrictions] [--frames N | --seconds S] [--wav PATH] [--wav-channels] [--sample-rate HZ] [--link-host ADDRESS | --link-join ADDRESS | --printer DIRECTORY] [ROM path]
+
+MODEL is one of dmg0, dmg, mgb, sgb, cgb or agb, by default cgb for games that support it and dmg otherwise.
+--force-dmg runs CGB games on a cgb or agb in DMG compatibility mode.
+--no-access-restrictions lets the CPU access VRAM and OAM while the PPU is using them.
+ADDRESS is either HOST:PORT for TCP or unix:PATH for a Unix socket.";
+
+#[derive(Debug)]
+struct Options {
+    rom_path: PathBuf,
+    /// Picked from the cartridge's CGB flag if not given.
+    model: Option<Model>,
+    force_dmg: bool,
+    /// Run this boot ROM first, otherwise start at `0x0100` as if it had already run.
+    boot_rom_path: Option<PathBuf>,
+    renderer: Renderer,
+    access_restrictions: bool,
+    /// Run without a window for this long, then exit.
+    headless: Option<RunLength>,
+    /// Record audio to a WAV file while running headless.
+    wav_path: Option<PathBuf>,
+    /// Also record each channel to its own WAV file.
+    wav_channels: bool,
+    sample_rate: u32,
+    /// Link with another emulator, either hosting (`true`) or joining at the address.
+    link: Option<(bool, String)>,
+    /// Connect a Game Boy Printer, saving prints to this directory.
+    printer: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Self {
+            rom_path: PathBuf::from("./roms/gb/tests/blargg/01-special.gb"),
+            model: None,
+            force_dmg: false,
+            boot_rom_path: None,
+            renderer: Renderer::Scanline,
+            access_restrictions: true,
+            headless: None,
+            wav_path: None,
+            wav_channels: false,
+            sample_rate: 48000,
+            link: None,
+            printer: None,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("Missing value for {name}"));
+            match arg.as_str() {
+                "--model" => options.model = Some(value("--model")?.parse()?),
+                "--force-dmg" => options.force_dmg = true,
+                "--boot-rom" => options.boot_rom_path = Some(value("--boot-rom")?.into()),
+                "--pixel-fifo" => options.renderer = Renderer::PixelFIFO,
+                "--no-access-restrictions" => options.access_restrictions = false,
+                "--frames" => {
+                    let frames = value("--frames")?
+                        .parse()
+                        .map_err(|err| format!("--frames: {err}"))?;
+                    options.headless = Some(RunLength::Frames(frames));
+                }
+                "--seconds" => {
+                    let seconds = value("--seconds")?
+                        .parse()
+                        .map_err(|err| format!("--seconds: {err}"))?;
+                    options.headless = Some(RunLength::Seconds(seconds));
+                }
+                "--wav" => options.wav_path = Some(value("--wav")?.into()),
+                "--wav-channels" => options.wav_channels = true,
+                "--sample-rate" => {
+                    options.sample_rate = value("--sample-rate")?
+                        .parse()
+                        .map_err(|err| format!("--sample-rate: {err}"))?;
+                    if !(1..=MAX_SAMPLE_RATE).contains(&options.sample_rate) {
+                        return Err(format!(
+                            "--sample-rate must be between 1 and {MAX_SAMPLE_RATE}"
+                        ));
+                    }
+                }
+                "--link-host" => options.link = Some((true, value("--link-host")?)),
+                "--link-join" => options.link = Some((false, value("--link-join")?)),
+                "--printer" => options.printer = Some(value("--printer")?.into()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ => options.rom_path = arg.into(),
+            }
+        }
+        if options.wav_path.is_some() && options.headless.is_none() {
+            return Err("--wav needs a length from --frames or --seconds".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Hosts or joins a link cable connection, waiting for the other emulator when hosting.
+fn connect_link(host: bool, address: &str) -> std::io::Result<SocketLink> {
+    match (address.strip_prefix("unix:"), host) {
+        #[cfg(unix)]
+        (Some(path), true) => SocketLink::listen_unix(path),
+        #[cfg(unix)]
+        (Some(path), false) => SocketLink::connect_unix(path),
+        #[cfg(not(unix))]
+        (Some(_), _) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Unix sockets are not supported on this platform",
+        )),
+        (None, true) => SocketLink::listen_tcp(address),
+        (None, false) => SocketLink::connect_tcp(address),
+    }
+}
+
+/// Prints any events worth telling the user about.
+fn report_events(emu: &mut GameboyEmulator) {
+    while let Some(event) = emu.poll_event() {
+        match event {
+            EmulatorEvent::IllegalOpcode { pc, opcode } => {
+                eprintln!("GB - Illegal opcode {opcode:#04X} at {pc:#06X}, the CPU has locked up!")
+            }
+            EmulatorEvent::SaveLoadFailed(kind) => {
+                eprintln!("GB - Unable to load save file: {kind}")
+            }
+            EmulatorEvent::SaveFailed(kind) => eprintln!("GB - Unable to write save file: {kind}"),
+            EmulatorEvent::PrintSaveFailed(kind) => {
+                eprintln!("GB - Printer: Unable to save print: {kind}")
+            }
+            EmulatorEvent::LinkDisconnected(kind) => {
+                eprintln!("GB - Link cable disconnected: {kind}")
+            }
+            EmulatorEvent::Rumble(_) => {}
+        }
+    }
+}
+
+/// Runs without a window, recording audio if asked to.
+fn run_headless(
+    mut emu: GameboyEmulator,
+    length: RunLength,
+    options: &Options,
+) -> std::io::Result<()> {
+    let mut sink = match &options.wav_path {
+        Some(path) => Some(WavSink::create(
+            path,
+            options.sample_rate,
+            options.wav_channels,
+        )?),
+        None => None,
+    };
+    emu.run_headless(length, JoypadState::default(), sink.as_mut())?;
+    report_events(&mut emu);
+    if let Some(sink) = sink {
+        sink.finalize()?;
+    }
+    emu.flush_save()
+}
+
+#[derive(Debug)]
+pub struct KeyBinds {
+    pub button_a: KeyCode,
+    pub button_b: KeyCode,
+    pub button_select: KeyCode,
+    pub button_start: KeyCode,
+
+    pub button_right: KeyCode,
+    pub button_left: KeyCode,
+    pub button_up: KeyCode,
+    pub button_down: KeyCode,
+}
+
+impl Default for KeyBinds {
+    fn default() -> Self {
+        Self {
+            button_a: KeyCode::KeyQ,
+            button_b: KeyCode::KeyE,
+            button_select: KeyCode::KeyZ,
+            button_start: KeyCode::KeyX,
+
+            button_right: KeyCode::KeyD,
+            button_left: KeyCode::KeyA,
+            button_up: KeyCode::KeyW,
+            button_down: KeyCode::KeyS,
+        }
+    }
+}
+
+impl KeyBinds {
+    /// Returns the state of the joypad from the currently held keys.
+    pub fn get_joypad_state(&self, input: &WinitInputHelper) -> JoypadState {
+        JoypadState {
+            a: input.key_held(self.button_a),
+            b: input.key_held(self.button_b),
+            select: input.key_held(self.button_select),
+            start: input.key_held(self.button_start),
 
-pub type RenderBuffer<'a> = Buffer<'a, Rc<Window>, Rc<Window>>;
+            right: input.key_held(self.button_right),
+            left: input.key_held(self.button_left),
+            up: input.key_held(self.button_up),
+            down: input.key_held(self.button_down),
+        }
+    }
+}
 
 fn main() -> Result<(), EventLoopError> {
+    let options = match Options::parse() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let cartridge = Cartridge::load_from_file(&options.rom_path).unwrap();
+    let model = options
+        .model
+        .unwrap_or(match cartridge.get_cgb_flag() & 0x80 {
+            0x00 => Model::DMG,
+            _ => Model::CGB,
+        });
+    let boot_rom = match &options.boot_rom_path {
+        Some(path) => match load_boot_rom(path, model) {
+            Ok(boot_rom) => Some(boot_rom),
+            Err(err) => {
+                eprintln!("Unable to load boot ROM: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let mut emu = GameboyEmulator::new_with_model(cartridge, model, boot_rom);
+    emu.force_dmg = options.force_dmg;
+    emu.ppu.renderer = options.renderer;
+    emu.ppu.access_restrictions = options.access_restrictions;
+    // ? Test ROMs print their results over serial.
+    let serial_output = SerialCapture::new();
+    emu.connect_serial(serial_output.clone());
+    if let Some(directory) = &options.printer {
+        emu.connect_serial(GameBoyPrinter::new(Some(PrintOutput {
+            directory: directory.clone(),
+            extension: "png",
+        })));
+    }
+    if let Some((host, address)) = &options.link {
+        if *host {
+            println!("Waiting for the other emulator to connect to {address}...");
+        }
+        match connect_link(*host, address) {
+            Ok(link) => emu.connect_serial(link),
+            Err(err) => {
+                eprintln!("Unable to connect link cable: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    let state_path = options.rom_path.with_extension("state");
+
+    if let Some(length) = options.headless {
+        let result = run_headless(emu, length, &options);
+        print!("{}", serial_output.get_text());
+        if let Err(err) = result {
+            eprintln!("Headless run failed: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (_, display_width, display_height) = emu.display();
     let event_loop = EventLoop::new().expect("Unable to create window!");
     let window = Rc::new(
         WindowBuilder::new()
             .with_title("Loki Emulator")
             .with_resizable(false)
-            .with_inner_size(PhysicalSize::new(160, 144))
+            .with_inner_size(PhysicalSize::new(
+                display_width as u32,
+                display_height as u32,
+            ))
             .build(&event_loop)
             .expect("Unable to create window!"),
     );
@@ -45,39 +314,57 @@ fn main() -> Result<(), EventLoopError> {
     let mut surface = Surface::new(&context, window.clone()).expect("Unable to create window!");
 
     let mut input = WinitInputHelper::new();
-
-    let mut emu = GameboyEmulator {
-        prev_update: Instant::now(),
-        cpu: CPU::new_init(),
-        ppu: PPU::new_init(),
-        ime: IME::Disabled,
-        is_halted: false,
-        bus: Bus {
-            cartridge: Cartridge::load_from_file("./roms/gb/tests/blargg/01-special.gb").unwrap(),
-            vram: VRAM::new_empty(),
-            wram: WRAM::new_empty(),
-            oam: OAM::new_empty(),
-            hram: HRAM::new_empty(),
-        },
-        io_registers: IORegisters::new(),
-        current_instruction: Instruction::default(),
-    };
-
-    Bus::write(&mut emu, 0xFF44, 0x90);
+    let key_binds = KeyBinds::default();
 
     if let Ok(title) = emu.bus.cartridge.get_title() {
         window.set_title(format!("Loki Emulator - {title}").as_str());
     }
 
+    let mut next_frame = Instant::now();
+
     event_loop.run(|event, elwt| {
-        elwt.set_control_flow(ControlFlow::Poll);
+        elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
 
         if input.update(&event) {
             if input.close_requested() {
+                if let Err(err) = emu.flush_save() {
+                    eprintln!("Unable to write save file: {err}");
+                }
+                print!("{}", serial_output.get_text());
                 elwt.exit();
                 return;
             }
 
+            let joypad = key_binds.get_joypad_state(&input);
+            if input.key_pressed(KeyCode::F5) {
+                emu.finish_instruction(joypad);
+                match emu.save_state() {
+                    Ok(state) => {
+                        if let Err(err) = std::fs::write(&state_path, state) {
+                            eprintln!("Unable to write save state: {err}");
+                        }
+                    }
+                    Err(err) => eprintln!("Unable to save state: {err}"),
+                }
+            }
+            if input.key_pressed(KeyCode::F8) {
+                match std::fs::read(&state_path) {
+                    Ok(state) => {
+                        if let Err(err) = emu.load_state(&state) {
+                            eprintln!("Unable to load state: {err}");
+                        }
+                    }
+                    Err(err) => eprintln!("Unable to read save state: {err}"),
+                }
+            }
+
+            let now = Instant::now();
+            if now < next_frame {
+                return;
+            }
+            next_frame = (next_frame + FRAME_DURATION).max(now);
+            elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+
             let (width, height) = {
                 let size = window.inner_size();
                 (size.width, size.height)
@@ -90,8 +377,19 @@ fn main() -> Result<(), EventLoopError> {
                 )
                 .unwrap();
 
+            emu.run_frame(joypad);
+            report_events(&mut emu);
+
+            // ? Nearest-neighbour scale the emulator's output to the window.
             let mut buffer = surface.buffer_mut().unwrap();
-            emu.update(&mut input, window.clone(), &mut buffer);
+            let (frame, frame_width, frame_height) = emu.display();
+            for y in 0..height as usize {
+                let src_y = y * frame_height / height as usize;
+                for x in 0..width as usize {
+                    let src_x = x * frame_width / width as usize;
+                    buffer[y * width as usize + x] = frame[src_y * frame_width + src_x];
+                }
+            }
             buffer.present().unwrap();
         }
     })